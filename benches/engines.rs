@@ -0,0 +1,99 @@
+//! Times the reference and fast engines on the puzzles that run the most Intcode. Run with
+//! `cargo bench`.
+
+use aoc::intcode::network::{Network, Wiring};
+use aoc::intcode::*;
+
+use itertools::Itertools;
+use std::time::{Duration, Instant};
+
+/// How many times each puzzle is run; the fastest run is reported.
+const ROUNDS: usize = 10;
+
+type Puzzle = fn(vm::Engine) -> isize;
+
+fn main() {
+    let puzzles: [(&str, Puzzle); 3] = [("day07", day07), ("day09", day09), ("day13", day13)];
+    for (name, puzzle) in puzzles.iter() {
+        let reference = time(|| puzzle(vm::Engine::Reference));
+        let fast = time(|| puzzle(vm::Engine::Fast));
+        println!(
+            "{}  reference {:>10.2?}  fast {:>10.2?}  ({:.1}x)",
+            name,
+            reference,
+            fast,
+            reference.as_secs_f64() / fast.as_secs_f64()
+        );
+    }
+}
+
+fn time(mut puzzle: impl FnMut() -> isize) -> Duration {
+    let answer = puzzle();
+    let rounds = (0..ROUNDS).map(|_| {
+        let start = Instant::now();
+        assert_eq!(puzzle(), answer);
+        start.elapsed()
+    });
+    rounds.min().unwrap()
+}
+
+fn program(input: &str) -> Program {
+    input.parse().unwrap()
+}
+
+/// Runs every ring of amplifiers, as in part 2. Each amplifier only runs a
+/// few dozen instructions, so the fast engine has no loops to win back the
+/// time it spends compiling them.
+fn day07(engine: vm::Engine) -> isize {
+    let program = program(include_str!("../input/day07.in"));
+    let signals = (5..=9).permutations(5).map(|settings| {
+        let mut machine = Machine::default_io(&program);
+        machine.engine = engine;
+        let mut network = Network::new(vec![machine; 5], Wiring::Ring);
+        for (machine, setting) in network.machines.iter_mut().zip(settings) {
+            machine.input.queue.push_back(setting.into());
+        }
+        network.inject(0, 0.into());
+        network.run();
+        network.outputs[4].last().unwrap().0
+    });
+    signals.max().unwrap()
+}
+
+/// Runs the BOOST program in sensor boost mode, as in part 2.
+fn day09(engine: vm::Engine) -> isize {
+    let mut machine = Machine::default_io(&program(include_str!("../input/day09.in")));
+    machine.engine = engine;
+    machine.input.queue.push_back(2.into());
+    assert_eq!(machine.run(), vm::Status::Halted);
+    machine.output.buffer[0].0
+}
+
+/// Plays the game to the end, keeping the paddle under the ball, as in
+/// part 2.
+fn day13(engine: vm::Engine) -> isize {
+    let mut machine = Machine::default_io(&program(include_str!("../input/day13.in")));
+    machine.engine = engine;
+    machine.memory[mem::Address(0)] = 2.into();
+    let (mut ball, mut paddle, mut score) = (0, 0, 0);
+    loop {
+        let status = machine.run();
+        for tile in machine.output.buffer.chunks(3) {
+            match *tile {
+                [mem::Value(-1), mem::Value(0), mem::Value(value)] => score = value,
+                [mem::Value(x), _, mem::Value(3)] => paddle = x,
+                [mem::Value(x), _, mem::Value(4)] => ball = x,
+                _ => (),
+            }
+        }
+        machine.output.buffer.clear();
+        match status {
+            vm::Status::Halted => return score,
+            vm::Status::Blocked => {
+                let joystick = (ball - paddle).signum();
+                machine.input.queue.push_back(joystick.into());
+            }
+            status => panic!("{:?}", status),
+        }
+    }
+}
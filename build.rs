@@ -0,0 +1,51 @@
+//! Translates the Intcode programs that are run natively into Rust, with
+//! `intcode::transpile`. The output goes in `OUT_DIR`, to be included from
+//! there.
+
+// A build script cannot use the crate it builds, so it compiles its own copy
+// of the intcode module, most of which goes unused here.
+#![allow(dead_code, unused_imports)]
+
+#[path = "src"]
+mod lib {
+    pub mod graph;
+    pub mod intcode;
+}
+
+// So that `crate::graph` and `crate::intcode` resolve as in the library.
+use lib::{graph, intcode};
+
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+fn main() {
+    for path in &["src/graph.rs", "src/intcode.rs", "src/intcode"] {
+        println!("cargo:rerun-if-changed={}", path);
+    }
+    let out_dir = env::var_os("OUT_DIR").unwrap();
+    let out_dir = Path::new(&out_dir);
+
+    println!("cargo:rerun-if-changed=input/day09.in");
+    intcode::transpile::transpile_file("input/day09.in", out_dir.join("day09.rs")).unwrap();
+
+    // Each known-answer program becomes a module, listed in `KNOWN_ANSWERS`
+    // along with its source.
+    let known_answers = fs::read_to_string("src/intcode/test/known_answers.in").unwrap();
+    let mut out = String::new();
+    let mut table = String::new();
+    for (index, source) in known_answers.lines().enumerate() {
+        let program = source.parse::<intcode::Program>().unwrap();
+        writeln!(out, "mod program{} {{", index).unwrap();
+        writeln!(out, "use crate::intcode;").unwrap();
+        out.push_str(&intcode::transpile::transpile(&program));
+        writeln!(out, "}}\n").unwrap();
+        writeln!(table, "    ({:?}, program{}::run),", source, index).unwrap();
+    }
+    writeln!(out, "const KNOWN_ANSWERS: &[(&str, Run)] = &[\n{}];", table).unwrap();
+    let path = out_dir.join("known_answers.rs");
+    if fs::read_to_string(&path).ok().as_ref() != Some(&out) {
+        fs::write(path, out).unwrap();
+    }
+}
@@ -8,17 +8,17 @@ fn main() {
 }
 
 fn part1(program: &Program) {
-    let value = run_machine(&program, 12.into(), 2.into());
+    let value = run_machine(program, 12.into(), 2.into());
     println!("{}", value.0);
 }
 
 fn part2(program: &Program) {
-    let input = run_all_combinations(&program);
+    let input = run_all_combinations(program);
     println!("{}", input);
 }
 
 fn run_machine(program: &Program, noun: mem::Value, verb: mem::Value) -> mem::Value {
-    let mut machine = Machine::default_io(&program);
+    let mut machine = Machine::default_io(program);
     machine.memory[mem::NOUN_ADDRESS] = noun;
     machine.memory[mem::VERB_ADDRESS] = verb;
     machine.run();
@@ -28,7 +28,7 @@ fn run_machine(program: &Program, noun: mem::Value, verb: mem::Value) -> mem::Va
 fn run_all_combinations(program: &Program) -> isize {
     for noun in 0..=99 {
         for verb in 0..=99 {
-            let value = run_machine(&program, noun.into(), verb.into());
+            let value = run_machine(program, noun.into(), verb.into());
             if value.0 == 19690720 {
                 return 100 * noun + verb;
             }
@@ -1,11 +1,10 @@
 use std::collections::HashMap;
 use std::hash::Hash;
-use std::iter;
 
 fn main() {
     let input = include_str!("../../input/day03.in");
-    part1(&input);
-    part2(&input);
+    part1(input);
+    part2(input);
 }
 
 fn part1(input: &str) {
@@ -71,7 +70,7 @@ fn visited_points(string: &str) -> HashMap<Point, usize> {
     let steps = string.split(',').flat_map(|segment| {
         let direction = segment.chars().next().unwrap();
         let distance = segment[1..].parse().unwrap();
-        iter::repeat(direction).take(distance)
+        std::iter::repeat_n(direction, distance)
     });
 
     let mut points = HashMap::new();
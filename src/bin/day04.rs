@@ -1,5 +1,3 @@
-#![feature(is_sorted)]
-
 use radixal::IntoDigits;
 use slice_group_by::GroupBy;
 
@@ -1,5 +1,3 @@
-#![feature(slice_patterns)]
-
 use aoc::intcode::*;
 
 fn main() {
@@ -10,17 +8,17 @@ fn main() {
 }
 
 fn part1(program: &Program) {
-    let output = run_machine(&program, 1.into());
+    let output = run_machine(program, 1.into());
     println!("{}", output.0);
 }
 
 fn part2(program: &Program) {
-    let output = run_machine(&program, 5.into());
+    let output = run_machine(program, 5.into());
     println!("{}", output.0);
 }
 
 fn run_machine(program: &Program, input: mem::Value) -> mem::Value {
-    let mut machine = Machine::default_io(&program);
+    let mut machine = Machine::default_io(program);
     machine.input.queue.push_back(input);
     machine.run();
     *machine.output.buffer.last().unwrap()
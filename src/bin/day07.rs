@@ -1,7 +1,8 @@
+use aoc::intcode::network::{Network, Wiring};
 use aoc::intcode::*;
 
 use itertools::Itertools;
-use std::iter;
+use std::ops::RangeInclusive;
 
 fn main() {
     let input = include_str!("../../input/day07.in");
@@ -11,78 +12,89 @@ fn main() {
 }
 
 fn part1(program: &Program) -> isize {
-    let possible_settings = (0..=4).permutations(5);
-    let mut running_max = 0;
-    for settings in possible_settings {
-        let machine = Machine::default_io(&program);
-        let mut machines = iter::repeat(machine).take(5).collect::<Vec<_>>();
-        for (index, machine) in machines.iter_mut().enumerate() {
-            machine.input.queue.push_back(settings[index].into());
-        }
-        machines[0].input.queue.push_back(0.into());
-        for i in 0..5 {
-            let prev_machine = &mut machines[(i + 4) % 5];
-            let prev_output = prev_machine.output.buffer.drain(..).collect::<Vec<_>>();
-            let machine = &mut machines[i];
-            machine.input.queue.extend(prev_output);
-            machine.run();
-        }
-        let output = machines[4].output.buffer.first().unwrap();
-        running_max = running_max.max(output.0);
-    }
-    running_max
+    max_signal(program, 0..=4, || Wiring::Pipeline)
 }
 
 fn part2(program: &Program) -> isize {
-    let possible_settings = (5..=9).permutations(5);
-    let mut running_max = 0;
-    for settings in possible_settings {
-        let machine = Machine::default_io(&program);
-        let mut machines = iter::repeat(machine).take(5).collect::<Vec<_>>();
-        for (index, machine) in machines.iter_mut().enumerate() {
-            machine.input.queue.push_back(settings[index].into());
-        }
-        machines[0].input.queue.push_back(0.into());
-        let mut outputs = vec![vec![]; 5];
-        'outer: loop {
-            for i in 0..5 {
-                let machine = &mut machines[i];
-                machine.input.queue.extend(outputs[(i + 4) % 5].drain(..));
-                let status = machine.run();
-                outputs[i].extend(machine.output.buffer.drain(..));
-                match status {
-                    vm::Status::Halted if i == 4 => {
-                        break 'outer;
-                    }
-                    vm::Status::Halted | vm::Status::Blocked => {
-                        continue;
-                    }
-                    vm::Status::Ready => unreachable!(),
-                }
-            }
+    max_signal(program, 5..=9, || Wiring::Ring)
+}
+
+fn max_signal(program: &Program, settings: RangeInclusive<isize>, wiring: fn() -> Wiring) -> isize {
+    let signals = settings.permutations(5).map(|settings| {
+        let mut network = Network::new(vec![Machine::default_io(program); 5], wiring());
+        for (machine, setting) in network.machines.iter_mut().zip(settings) {
+            machine.input.queue.push_back(setting.into());
         }
-        let output = outputs[4].last().expect("machine 4 should have output");
-        running_max = running_max.max(output.0);
-    }
-    running_max
+        network.machines[0].input.queue.push_back(0.into());
+        network.run();
+        network.outputs[4]
+            .last()
+            .expect("machine 4 should have output")
+            .0
+    });
+    signals.max().unwrap()
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    use std::iter;
+
+    /// Runs the amplifiers as tasks on an executor, feeding the last one back
+    /// into the first if `ring` is set, and returns the last signal sent.
+    fn async_signal(program: &Program, settings: &[isize], ring: bool) -> isize {
+        let mut executor = task::Executor::new();
+        let (mut senders, mut inputs): (Vec<_>, Vec<_>) = (0..6).map(|_| task::pipe()).unzip();
+        let last = inputs.pop().unwrap();
+        for (sender, &setting) in senders.iter_mut().zip(settings) {
+            sender.write_output(setting.into());
+        }
+        senders[0].write_output(0.into());
+        let amplifiers = inputs
+            .into_iter()
+            .enumerate()
+            .map(|(index, input)| {
+                let to = match index {
+                    4 if ring => 0,
+                    _ => index + 1,
+                };
+                let machine = Machine::default_io(program).with_io(input, senders[to].clone());
+                executor.spawn(task::run(machine))
+            })
+            .collect::<Vec<_>>();
+        drop(senders);
+        executor.run();
+        let mut signals = match ring {
+            true => amplifiers[0].take().unwrap().input,
+            false => last,
+        };
+        iter::from_fn(|| signals.read_input())
+            .last()
+            .expect("machine 4 should have output")
+            .0
+    }
+
+    fn max_async_signal(program: &Program, settings: RangeInclusive<isize>, ring: bool) -> isize {
+        let signals = settings.permutations(5);
+        let signals = signals.map(|settings| async_signal(program, &settings, ring));
+        signals.max().unwrap()
+    }
+
     #[test]
     fn test_part1() {
         let program = Program::from(&[
             3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0,
         ]);
         assert_eq!(part1(&program), 43210);
+        assert_eq!(max_async_signal(&program, 0..=4, false), 43210);
 
         let program = Program::from(&[
             3, 23, 3, 24, 1002, 24, 10, 24, 1002, 23, -1, 23, 101, 5, 23, 23, 1, 24, 23, 23, 4, 23,
             99, 0, 0,
         ]);
         assert_eq!(part1(&program), 54321);
+        assert_eq!(max_async_signal(&program, 0..=4, false), 54321);
 
         let program = Program::from(
             [
@@ -92,6 +104,7 @@ mod test {
             .iter(),
         );
         assert_eq!(part1(&program), 65210);
+        assert_eq!(max_async_signal(&program, 0..=4, false), 65210);
     }
 
     #[test]
@@ -104,6 +117,7 @@ mod test {
             .iter(),
         );
         assert_eq!(part2(&program), 139629729);
+        assert_eq!(max_async_signal(&program, 5..=9, true), 139629729);
     }
 
     #[test]
@@ -117,5 +131,14 @@ mod test {
             .iter(),
         );
         assert_eq!(part2(&program), 18216);
+        assert_eq!(max_async_signal(&program, 5..=9, true), 18216);
+    }
+
+    #[test]
+    fn test_async_matches_puzzle() {
+        let program = include_str!("../../input/day07.in")
+            .parse::<Program>()
+            .unwrap();
+        assert_eq!(max_async_signal(&program, 5..=9, true), part2(&program));
     }
 }
@@ -14,7 +14,7 @@ fn main() {
 }
 
 fn part1(input: &[u32]) {
-    let mut min_freqs = [std::usize::MAX; 3];
+    let mut min_freqs = [usize::MAX; 3];
     'chunks: for chunk in input.chunks(LAYER_SIZE) {
         let mut freqs = [0; 3];
         for x in chunk {
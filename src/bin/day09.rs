@@ -1,5 +1,10 @@
 use aoc::intcode::*;
 
+mod native {
+    use aoc::intcode;
+    include!(concat!(env!("OUT_DIR"), "/day09.rs"));
+}
+
 fn main() {
     let input = include_str!("../../input/day09.in");
     let program = input.parse::<Program>().unwrap();
@@ -8,19 +13,19 @@ fn main() {
 }
 
 fn part1(program: &Program) {
-    let value = run_with_input(&program, 1.into());
+    let value = run_with_input(program, 1.into());
     println!("{}", value.0);
 }
 
 fn part2(program: &Program) {
-    let value = run_with_input(&program, 2.into());
+    let value = run_with_input(program, 2.into());
     println!("{}", value.0);
 }
 
 fn run_with_input(program: &Program, input: mem::Value) -> mem::Value {
-    let mut machine = Machine::default_io(&program);
+    let mut machine = Machine::default_io(program);
     machine.input.queue.push_back(input);
-    let status = machine.run();
+    let status = native::run(&mut machine);
     assert_eq!(status, vm::Status::Halted);
     *machine.output.buffer.first().unwrap()
 }
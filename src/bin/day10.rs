@@ -12,7 +12,7 @@ fn main() {
 struct Asteroids(Vec<Point>);
 
 impl FromStr for Asteroids {
-    type Err = !;
+    type Err = std::convert::Infallible;
     fn from_str(string: &str) -> Result<Self, Self::Err> {
         let mut points = vec![];
         for (y, row) in string.trim().lines().enumerate() {
@@ -33,11 +33,11 @@ impl Asteroids {
             if point == from {
                 continue;
             }
-            let slope = from.slope_to(&point);
+            let slope = from.slope_to(point);
             let entry = points_by_slope.entry(slope).or_insert(Vec::new());
             entry.push(*point);
         }
-        for (_slope, points) in &mut points_by_slope {
+        for points in points_by_slope.values_mut() {
             points.sort_unstable_by(|p1, p2| {
                 let d1 = from.distance_to(p1);
                 let d2 = from.distance_to(p2);
@@ -61,6 +61,8 @@ fn part1(asteroids: &Asteroids) {
     println!("{} from {:?}", visible, point);
 }
 
+// Unfinished, so not called from `main` yet.
+#[allow(dead_code, unused_variables, clippy::needless_ifs)]
 fn part2(asteroids: &Asteroids) {
     let (point, _) = asteroids.most_visible_point();
     let visible = asteroids.visible_points(&point);
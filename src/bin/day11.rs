@@ -51,7 +51,7 @@ struct Robot {
 impl Robot {
     fn new(program: &Program, start: mem::Value) -> Self {
         let mut robot = Robot {
-            machine: Machine::default_io(&program),
+            machine: Machine::default_io(program),
             position: Point(0, 0),
             heading: Direction::Up,
             white_panels: HashSet::new(),
@@ -101,7 +101,7 @@ impl Robot {
                     self.paint(color);
                     self.turn_and_advance(turn);
                 }
-                vm::Status::Ready => {
+                vm::Status::Ready | vm::Status::OutOfFuel => {
                     continue;
                 }
                 vm::Status::Blocked => {
@@ -111,6 +111,9 @@ impl Robot {
                 vm::Status::Halted => {
                     break;
                 }
+                vm::Status::Faulted(fault) => {
+                    panic!("{}", fault);
+                }
             }
         }
     }
@@ -126,5 +129,6 @@ fn main() {
 
 #[cfg(test)]
 mod test {
+    #[allow(unused_imports)]
     use super::*;
 }
@@ -11,7 +11,7 @@ fn main() {
 }
 
 fn part1(moons: &Moons) {
-    let mut moons = moons.clone();
+    let mut moons = *moons;
     for _ in 0..1000 {
         moons.step();
     }
@@ -19,8 +19,8 @@ fn part1(moons: &Moons) {
 }
 
 fn part2(moons: &Moons) {
-    let original = moons.clone();
-    let mut moons = moons.clone();
+    let original = *moons;
+    let mut moons = *moons;
     let mut steps = 0;
     let mut steps_to_repeat = [None; 3];
     let (x, y, z) = loop {
@@ -30,7 +30,7 @@ fn part2(moons: &Moons) {
         moons.step();
         steps += 1;
         for &axis in &[Axis::X, Axis::Y, Axis::Z] {
-            if steps_to_repeat[axis as usize] == None {
+            if steps_to_repeat[axis as usize].is_none() {
                 let same_pos = moons.positions(axis) == original.positions(axis);
                 let same_vel = moons.velocities(axis) == original.velocities(axis);
                 if same_pos && same_vel {
@@ -165,7 +165,7 @@ impl Moons {
 }
 
 impl FromStr for Moons {
-    type Err = !;
+    type Err = std::convert::Infallible;
     fn from_str(string: &str) -> Result<Self, Self::Err> {
         let points = string.lines().map(Point3D::from_str).filter_map(Result::ok);
         Ok(Moons::new(&points.collect::<Vec<_>>()))
@@ -1,3 +1,6 @@
+// num-derive 0.3 puts the impls it derives inside a `const _` block.
+#![allow(non_local_definitions)]
+
 use aoc::intcode::*;
 
 use num_derive::FromPrimitive;
@@ -12,7 +15,7 @@ fn main() {
 }
 
 fn part1(program: &Program) {
-    let mut machine = Machine::<DefaultInput, Game>::new(&program);
+    let mut machine = Machine::<DefaultInput, Game>::new(program);
     machine.run();
     let blocks = machine
         .output
@@ -24,7 +27,7 @@ fn part1(program: &Program) {
 }
 
 fn part2(program: &Program) {
-    let mut machine = Machine::<DefaultInput, Game>::new(&program);
+    let mut machine = Machine::<DefaultInput, Game>::new(program);
     machine.memory[mem::Address(0)] = 2.into();
     loop {
         match machine.run() {
@@ -33,7 +36,8 @@ fn part2(program: &Program) {
                 .input
                 .queue
                 .push_back(machine.output.read_input().unwrap()),
-            vm::Status::Ready => continue,
+            vm::Status::Ready | vm::Status::OutOfFuel => continue,
+            vm::Status::Faulted(fault) => panic!("{}", fault),
         }
     }
     println!("{}", machine.output.score);
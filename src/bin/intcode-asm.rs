@@ -0,0 +1,33 @@
+//! Assembles an Intcode program, writing it out in comma-separated form.
+//!
+//! Usage: `intcode-asm [SOURCE]`, reading the source from stdin if no path
+//! is given.
+
+use aoc::intcode::asm;
+
+use std::io::{self, Read};
+use std::{env, fs, process};
+
+fn main() {
+    let (name, source) = match env::args().nth(1) {
+        Some(path) => {
+            let source = fs::read_to_string(&path).unwrap_or_else(|err| {
+                eprintln!("intcode-asm: {}: {}", path, err);
+                process::exit(1);
+            });
+            (path, source)
+        }
+        None => {
+            let mut source = String::new();
+            io::stdin().read_to_string(&mut source).unwrap();
+            ("<stdin>".to_owned(), source)
+        }
+    };
+    match asm::assemble(&source) {
+        Ok(program) => println!("{}", program),
+        Err(err) => {
+            eprintln!("intcode-asm: {}: {}", name, err);
+            process::exit(1);
+        }
+    }
+}
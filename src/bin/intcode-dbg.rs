@@ -0,0 +1,523 @@
+//! An interactive debugger for Intcode programs.
+//!
+//! Usage: `intcode-dbg PROGRAM`, then type `help` at the prompt.
+
+use aoc::intcode::history::History;
+use aoc::intcode::trace::{StoreTrace, Trace, Tracer};
+use aoc::intcode::*;
+
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+use std::{env, fs, process};
+
+const HELP: &str = "\
+step [N]              execute N instructions (default 1)
+continue              run until a breakpoint, watchpoint, block, halt or fault
+back [N]              undo the last N instructions or edits (default 1)
+rewind ADDR           undo instructions and edits until the next one is at ADDR
+break ADDR|MNEMONIC   stop before executing at ADDR or any MNEMONIC instruction
+watch ADDR            stop after any write to ADDR
+unwatch ADDR          remove a watchpoint
+delete ADDR|MNEMONIC  remove a breakpoint
+info                  show the machine state and all breakpoints
+list [ADDR] [N]       show N instructions from ADDR (default: the next 5)
+x ADDR [N]            show N values of memory from ADDR (default 1)
+set ADDR VALUE        write VALUE to memory at ADDR
+set rb VALUE          set the relative base
+set ip ADDR           set the instruction pointer
+input VALUE...        queue values for the program to read
+output [clear]        show (or discard) the values the program has written
+quit                  exit the debugger";
+
+/// Records writes to watched addresses.
+#[derive(Clone, Debug, Default)]
+struct Watchpoints {
+    addresses: BTreeSet<mem::Address>,
+    hits: Vec<StoreTrace>,
+}
+
+impl Tracer for Watchpoints {
+    fn trace(&mut self, trace: &Trace) {
+        if let Some(store) = trace.store {
+            if self.addresses.contains(&store.address) {
+                self.hits.push(store);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Breakpoint {
+    Address(mem::Address),
+    Opcode(op::Opcode),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Command {
+    Step(usize),
+    Back(usize),
+    Rewind(mem::Address),
+    Continue,
+    Break(Breakpoint),
+    Watch(mem::Address),
+    Unwatch(mem::Address),
+    Delete(Breakpoint),
+    Info,
+    List(Option<mem::Address>, usize),
+    Examine(mem::Address, usize),
+    Poke(mem::Address, mem::Value),
+    SetRelBase(mem::Offset),
+    SetInsPtr(mem::Address),
+    Input(Vec<mem::Value>),
+    Output { clear: bool },
+    Help,
+    Quit,
+}
+
+fn parse_number<T: std::str::FromStr>(word: Option<&str>) -> Result<T, String> {
+    let word = word.ok_or("missing argument")?;
+    word.parse()
+        .map_err(|_| format!("invalid number {:?}", word))
+}
+
+fn parse_count(word: Option<&str>, default: usize) -> Result<usize, String> {
+    match word {
+        Some(_) => parse_number(word),
+        None => Ok(default),
+    }
+}
+
+fn parse_address(word: Option<&str>) -> Result<mem::Address, String> {
+    parse_number(word).map(mem::Address)
+}
+
+fn parse_breakpoint(word: Option<&str>) -> Result<Breakpoint, String> {
+    match word.and_then(op::Opcode::from_mnemonic) {
+        Some(opcode) => Ok(Breakpoint::Opcode(opcode)),
+        None => parse_address(word).map(Breakpoint::Address),
+    }
+}
+
+fn parse_command(line: &str) -> Result<Option<Command>, String> {
+    let mut words = line.split_whitespace();
+    let name = match words.next() {
+        Some(name) => name,
+        None => return Ok(None),
+    };
+    let command = match name {
+        "s" | "step" => Command::Step(parse_count(words.next(), 1)?),
+        "c" | "continue" => Command::Continue,
+        "back" => Command::Back(parse_count(words.next(), 1)?),
+        "rewind" => Command::Rewind(parse_address(words.next())?),
+        "b" | "break" => Command::Break(parse_breakpoint(words.next())?),
+        "w" | "watch" => Command::Watch(parse_address(words.next())?),
+        "unwatch" => Command::Unwatch(parse_address(words.next())?),
+        "d" | "delete" => Command::Delete(parse_breakpoint(words.next())?),
+        "i" | "info" => Command::Info,
+        "l" | "list" => match words.next() {
+            Some(word) => Command::List(
+                Some(parse_address(Some(word))?),
+                parse_count(words.next(), 5)?,
+            ),
+            None => Command::List(None, 5),
+        },
+        "x" => {
+            let address = parse_address(words.next())?;
+            Command::Examine(address, parse_count(words.next(), 1)?)
+        }
+        "set" => match words.next() {
+            Some("rb") => Command::SetRelBase(mem::Offset(parse_number(words.next())?)),
+            Some("ip") => Command::SetInsPtr(parse_address(words.next())?),
+            word => {
+                let address = parse_address(word)?;
+                Command::Poke(address, mem::Value(parse_number(words.next())?))
+            }
+        },
+        "in" | "input" => {
+            let values = words.by_ref().map(|word| parse_number(Some(word)));
+            Command::Input(
+                values
+                    .map(|value| value.map(mem::Value))
+                    .collect::<Result<_, _>>()?,
+            )
+        }
+        "out" | "output" => match words.next() {
+            Some("clear") => Command::Output { clear: true },
+            None => Command::Output { clear: false },
+            Some(word) => return Err(format!("unexpected argument {:?}", word)),
+        },
+        "h" | "help" => Command::Help,
+        "q" | "quit" => Command::Quit,
+        _ => return Err(format!("unknown command {:?}, try \"help\"", name)),
+    };
+    match words.next() {
+        Some(word) => Err(format!("unexpected argument {:?}", word)),
+        None => Ok(Some(command)),
+    }
+}
+
+struct Debugger {
+    machine: Machine<DefaultInput, DefaultOutput, (History, Watchpoints)>,
+    breakpoints: Vec<Breakpoint>,
+}
+
+impl Debugger {
+    fn new(program: &Program) -> Self {
+        Debugger {
+            machine: Machine::new(program),
+            breakpoints: vec![],
+        }
+    }
+
+    fn at_breakpoint(&self) -> bool {
+        let ins_ptr = self.machine.ins_ptr;
+        let opcode = self
+            .machine
+            .memory
+            .read_instruction(ins_ptr)
+            .map(|instruction| instruction.opcode());
+        self.breakpoints.iter().any(|breakpoint| match breakpoint {
+            Breakpoint::Address(address) => *address == ins_ptr,
+            Breakpoint::Opcode(breakpoint) => opcode == Ok(*breakpoint),
+        })
+    }
+
+    /// Steps up to `limit` times, stopping early at a breakpoint (other than
+    /// one at the starting instruction), a watchpoint, or any stopped status.
+    fn run(&mut self, limit: Option<usize>, out: &mut impl Write) -> io::Result<()> {
+        let mut steps = 0;
+        loop {
+            let status = self.machine.step();
+            steps += 1;
+            let watched = !self.machine.tracer.1.hits.is_empty();
+            for hit in self.machine.tracer.1.hits.drain(..) {
+                writeln!(
+                    out,
+                    "watchpoint: {} -> {} (was {})",
+                    hit.value.0, hit.address.0, hit.previous.0
+                )?;
+            }
+            if status != vm::Status::Ready {
+                writeln!(out, "{}", describe(status))?;
+                break;
+            }
+            if watched {
+                break;
+            }
+            if self.at_breakpoint() {
+                writeln!(out, "breakpoint at {}", self.machine.ins_ptr.0)?;
+                break;
+            }
+            if limit == Some(steps) {
+                break;
+            }
+        }
+        self.show_next(out)
+    }
+
+    fn show_next(&self, out: &mut impl Write) -> io::Result<()> {
+        self.list(self.machine.ins_ptr, 1, out)
+    }
+
+    fn list(&self, start: mem::Address, count: usize, out: &mut impl Write) -> io::Result<()> {
+        let mut address = start;
+        for _ in 0..count {
+            let marker = if address == self.machine.ins_ptr {
+                "=>"
+            } else {
+                "  "
+            };
+            match self.machine.memory.read_instruction(address) {
+                Ok(instruction) => {
+                    writeln!(out, "{} {:04}  {}", marker, address.0, instruction)?;
+                    address += instruction.opcode().len();
+                }
+                Err(err) => {
+                    writeln!(out, "{} {:04}  .data {}", marker, address.0, err.value.0)?;
+                    address += mem::Offset(1);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, command: Command, out: &mut impl Write) -> io::Result<bool> {
+        match command {
+            Command::Step(count) => {
+                if count > 0 {
+                    self.run(Some(count), out)?;
+                }
+            }
+            Command::Continue => self.run(None, out)?,
+            Command::Back(count) => {
+                let undone = (0..count).take_while(|_| self.machine.step_back()).count();
+                if undone < count {
+                    writeln!(out, "reached the start of the history")?;
+                }
+                self.show_next(out)?;
+            }
+            Command::Rewind(address) => {
+                if !self.machine.run_back_to(address) {
+                    writeln!(out, "reached the start of the history")?;
+                }
+                self.show_next(out)?;
+            }
+            Command::Break(breakpoint) => {
+                if !self.breakpoints.contains(&breakpoint) {
+                    self.breakpoints.push(breakpoint);
+                }
+            }
+            Command::Watch(address) => {
+                self.machine.tracer.1.addresses.insert(address);
+            }
+            Command::Unwatch(address) => {
+                if !self.machine.tracer.1.addresses.remove(&address) {
+                    writeln!(out, "no such watchpoint")?;
+                }
+            }
+            Command::Delete(breakpoint) => {
+                let before = self.breakpoints.len();
+                self.breakpoints.retain(|b| *b != breakpoint);
+                if self.breakpoints.len() == before {
+                    writeln!(out, "no such breakpoint")?;
+                }
+            }
+            Command::Info => {
+                let machine = &self.machine;
+                writeln!(out, "status:      {}", describe(machine.status))?;
+                writeln!(out, "ins_ptr:     {}", machine.ins_ptr.0)?;
+                writeln!(out, "rel_base:    {}", machine.memory.rel_base.0)?;
+                writeln!(out, "retired:     {}", machine.retired)?;
+                writeln!(out, "history:     {}", machine.tracer.0.len())?;
+                let input = machine.input.queue.iter().map(|value| value.0.to_string());
+                writeln!(out, "input:       {}", input.collect::<Vec<_>>().join(" "))?;
+                for breakpoint in &self.breakpoints {
+                    match breakpoint {
+                        Breakpoint::Address(address) => writeln!(out, "break {}", address.0)?,
+                        Breakpoint::Opcode(opcode) => writeln!(out, "break {}", opcode.mnemonic())?,
+                    }
+                }
+                for address in &machine.tracer.1.addresses {
+                    writeln!(out, "watch {}", address.0)?;
+                }
+            }
+            Command::List(start, count) => {
+                self.list(start.unwrap_or(self.machine.ins_ptr), count, out)?;
+            }
+            Command::Examine(start, count) => {
+                let addresses = (0..count).map_while(|index| start.0.checked_add(index));
+                for address in addresses.map(mem::Address) {
+                    writeln!(out, "{:04}  {}", address.0, self.machine.memory[address].0)?;
+                }
+            }
+            Command::Poke(address, value) => self.machine.poke(address, value),
+            Command::SetRelBase(offset) => self.machine.set_rel_base(offset),
+            Command::SetInsPtr(address) => {
+                self.machine.set_ins_ptr(address);
+                self.show_next(out)?;
+            }
+            Command::Input(values) => self.machine.input.queue.extend(values),
+            Command::Output { clear } => {
+                let output = self.machine.output.buffer.iter().map(|v| v.0.to_string());
+                writeln!(out, "{}", output.collect::<Vec<_>>().join(" "))?;
+                // Stepping back takes outputs back off the buffer, so it has
+                // to keep them while there is history to step back through.
+                if clear && !self.machine.tracer.0.is_empty() {
+                    writeln!(out, "cannot clear output while there is history")?;
+                } else if clear {
+                    self.machine.output.buffer.clear();
+                }
+            }
+            Command::Help => writeln!(out, "{}", HELP)?,
+            Command::Quit => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+fn describe(status: vm::Status) -> String {
+    match status {
+        vm::Status::Ready => "ready".to_owned(),
+        vm::Status::Blocked => "blocked on input".to_owned(),
+        vm::Status::Halted => "halted".to_owned(),
+        vm::Status::Faulted(fault) => fault.to_string(),
+        vm::Status::OutOfFuel => "out of fuel".to_owned(),
+    }
+}
+
+fn main() {
+    let path = env::args().nth(1).unwrap_or_else(|| {
+        eprintln!("usage: intcode-dbg PROGRAM");
+        process::exit(1);
+    });
+    let program = fs::read_to_string(&path)
+        .map_err(|err| err.to_string())
+        .and_then(|input| input.parse::<Program>().map_err(|err| err.to_string()))
+        .unwrap_or_else(|err| {
+            eprintln!("intcode-dbg: {}: {}", path, err);
+            process::exit(1);
+        });
+
+    let mut debugger = Debugger::new(&program);
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    debugger.show_next(&mut out).unwrap();
+    loop {
+        write!(out, "(dbg) ").unwrap();
+        out.flush().unwrap();
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap() == 0 {
+            break;
+        }
+        let keep_going = match parse_command(&line) {
+            Ok(Some(command)) => debugger.execute(command, &mut out).unwrap(),
+            Ok(None) => true,
+            Err(err) => {
+                writeln!(out, "error: {}", err).unwrap();
+                true
+            }
+        };
+        if !keep_going {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn run(debugger: &mut Debugger, lines: &[&str]) -> String {
+        let mut out = vec![];
+        for line in lines {
+            let command = parse_command(line).unwrap().unwrap();
+            debugger.execute(command, &mut out).unwrap();
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(parse_command("  "), Ok(None));
+        assert_eq!(parse_command("step"), Ok(Some(Command::Step(1))));
+        assert_eq!(parse_command("s 10"), Ok(Some(Command::Step(10))));
+        assert_eq!(
+            parse_command("break out"),
+            Ok(Some(Command::Break(Breakpoint::Opcode(op::Opcode::Output))))
+        );
+        assert_eq!(
+            parse_command("b 12"),
+            Ok(Some(Command::Break(Breakpoint::Address(mem::Address(12)))))
+        );
+        assert_eq!(
+            parse_command("set rb -3"),
+            Ok(Some(Command::SetRelBase(mem::Offset(-3))))
+        );
+        assert_eq!(
+            parse_command("set 4 -3"),
+            Ok(Some(Command::Poke(mem::Address(4), mem::Value(-3))))
+        );
+        assert_eq!(
+            parse_command("input 1 2"),
+            Ok(Some(Command::Input(vec![1.into(), 2.into()])))
+        );
+        assert!(parse_command("step x").is_err());
+        assert!(parse_command("info now").is_err());
+        assert!(parse_command("frobnicate").is_err());
+    }
+
+    #[test]
+    fn test_breakpoints_and_watchpoints() {
+        // Reads a value, then outputs it doubled until a counter runs out.
+        let program = Program::from(&[
+            3, 20, 1002, 20, 2, 21, 4, 21, 1001, 22, -1, 22, 1005, 22, 6, 99, 0, 0, 0, 0, 0, 0, 2,
+        ]);
+        let mut debugger = Debugger::new(&program);
+
+        let out = run(&mut debugger, &["continue"]);
+        assert_eq!(out, "blocked on input\n=> 0000  in 20\n");
+
+        let out = run(&mut debugger, &["input 21", "break out", "continue"]);
+        assert_eq!(out, "breakpoint at 6\n=> 0006  out 21\n");
+
+        let out = run(&mut debugger, &["watch 22", "continue"]);
+        assert_eq!(out, "watchpoint: 1 -> 22 (was 2)\n=> 0012  jt 22, #6\n");
+
+        let out = run(&mut debugger, &["break 22", "delete 22", "delete 22"]);
+        assert_eq!(out, "no such breakpoint\n");
+        assert!(debugger
+            .machine
+            .tracer
+            .1
+            .addresses
+            .contains(&mem::Address(22)));
+
+        let out = run(
+            &mut debugger,
+            &[
+                "unwatch 22",
+                "unwatch 22",
+                "delete out",
+                "continue",
+                "output",
+            ],
+        );
+        assert_eq!(out, "no such watchpoint\nhalted\n=> 0015  hlt\n42 42\n");
+    }
+
+    #[test]
+    fn test_inspect_and_poke() {
+        let program = Program::from(&[109, 3, 204, 0, 99, 0]);
+        let mut debugger = Debugger::new(&program);
+        let out = run(
+            &mut debugger,
+            &["step", "set rb 5", "set 5 7", "x 4 2", "c", "out"],
+        );
+        assert_eq!(
+            out,
+            "=> 0002  out [0]\n0004  99\n0005  7\nhalted\n=> 0004  hlt\n7\n"
+        );
+        assert_eq!(debugger.machine.memory.rel_base, mem::Offset(5));
+
+        let out = run(&mut debugger, &["rewind 2", "back 2", "x 5", "info"]);
+        assert!(out.starts_with("=> 0002  out [0]\n=> 0002  out [0]\n0005  0\n"));
+        assert!(out.contains("rel_base:    3\n"));
+    }
+
+    #[test]
+    fn test_back_and_rewind() {
+        let program = Program::from(&[4, 12, 1001, 12, -1, 12, 1005, 12, 0, 99, 0, 0, 3]);
+        let mut debugger = Debugger::new(&program);
+        let out = run(&mut debugger, &["c", "back", "rewind 2", "x 12", "out"]);
+        assert_eq!(
+            out,
+            "halted\n=> 0009  hlt\n=> 0009  hlt\n=> 0002  add 12, #-1, 12\n0012  1\n3 2 1\n"
+        );
+        let out = run(&mut debugger, &["back 100"]);
+        assert_eq!(out, "reached the start of the history\n=> 0000  out 12\n");
+        assert!(debugger.machine.output.buffer.is_empty());
+    }
+
+    #[test]
+    fn test_output_clear_keeps_history() {
+        let program = Program::from(&[104, 1, 104, 2, 99]);
+        let mut debugger = Debugger::new(&program);
+        let out = run(&mut debugger, &["c", "out clear", "back 2", "out"]);
+        assert_eq!(
+            out,
+            "halted\n=> 0004  hlt\n1 2\ncannot clear output while there is history\n\
+             => 0002  out #2\n1\n"
+        );
+        let out = run(&mut debugger, &["back", "out clear", "c", "out"]);
+        assert_eq!(out, "=> 0000  out #1\n\nhalted\n=> 0004  hlt\n1 2\n");
+    }
+
+    #[test]
+    fn test_examine_stops_at_the_last_address() {
+        let program = Program::from(&[99]);
+        let mut debugger = Debugger::new(&program);
+        let out = run(&mut debugger, &[&format!("x {} 3", usize::MAX - 1)]);
+        assert_eq!(out, format!("{}  0\n{}  0\n", usize::MAX - 1, usize::MAX));
+    }
+}
@@ -0,0 +1,34 @@
+//! Disassembles a comma-separated Intcode program into an annotated listing,
+//! which `intcode-asm` assembles back into the same program.
+//!
+//! Usage: `intcode-disasm [PROGRAM]`, reading the program from stdin if no
+//! path is given.
+
+use aoc::intcode::{disasm, Program};
+
+use std::io::{self, Read};
+use std::{env, fs, process};
+
+fn main() {
+    let (name, input) = match env::args().nth(1) {
+        Some(path) => {
+            let input = fs::read_to_string(&path).unwrap_or_else(|err| {
+                eprintln!("intcode-disasm: {}: {}", path, err);
+                process::exit(1);
+            });
+            (path, input)
+        }
+        None => {
+            let mut input = String::new();
+            io::stdin().read_to_string(&mut input).unwrap();
+            ("<stdin>".to_owned(), input)
+        }
+    };
+    match input.parse::<Program>() {
+        Ok(program) => print!("{}", disasm::disassemble(&program)),
+        Err(err) => {
+            eprintln!("intcode-disasm: {}: {}", name, err);
+            process::exit(1);
+        }
+    }
+}
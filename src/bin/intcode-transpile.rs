@@ -0,0 +1,20 @@
+//! Translates a comma-separated Intcode program into Rust source, which runs
+//! the program natively when included next to `aoc::intcode`.
+//!
+//! Usage: `intcode-transpile PROGRAM OUTPUT`.
+
+use aoc::intcode::transpile;
+
+use std::{env, process};
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    if args.len() != 2 {
+        eprintln!("usage: intcode-transpile PROGRAM OUTPUT");
+        process::exit(1);
+    }
+    if let Err(err) = transpile::transpile_file(&args[0], &args[1]) {
+        eprintln!("intcode-transpile: {}: {}", args[0], err);
+        process::exit(1);
+    }
+}
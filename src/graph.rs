@@ -21,7 +21,7 @@ impl<T> Graph<T> {
     pub fn add_node(&mut self, value: T) -> NodeIndex {
         let index = NodeIndex(self.nodes.len());
         self.nodes.push(Node {
-            value: value,
+            value,
             first_outgoing_edge: None,
         });
         index
@@ -31,14 +31,26 @@ impl<T> Graph<T> {
         let edge_index = EdgeIndex(self.edges.len());
         let node_data = &mut self.nodes[source.0];
         self.edges.push(Edge {
-            target: target,
+            target,
             next_outgoing_edge: node_data.first_outgoing_edge,
         });
         node_data.first_outgoing_edge = Some(edge_index);
         edge_index
     }
 
-    pub fn successors(&self, source: NodeIndex) -> Successors<T> {
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn node_indices(&self) -> impl Iterator<Item = NodeIndex> {
+        (0..self.nodes.len()).map(NodeIndex)
+    }
+
+    pub fn successors(&self, source: NodeIndex) -> Successors<'_, T> {
         let first_outgoing_edge = self.nodes[source.0].first_outgoing_edge;
         Successors {
             graph: self,
@@ -46,17 +58,23 @@ impl<T> Graph<T> {
         }
     }
 
-    pub fn bfs(&self, source: NodeIndex) -> Bfs<T> {
+    pub fn bfs(&self, source: NodeIndex) -> Bfs<'_, T> {
         let mut queue = VecDeque::new();
         queue.push_back((source, 0));
         Bfs {
             graph: self,
-            queue: queue,
+            queue,
             visited: HashSet::new(),
         }
     }
 }
 
+impl<T> Default for Graph<T> {
+    fn default() -> Self {
+        Graph::new()
+    }
+}
+
 impl<T: PartialEq> Graph<T> {
     pub fn find_node(&self, value: &T) -> Option<NodeIndex> {
         self.nodes.iter().enumerate().find_map(|(index, node)| {
@@ -147,6 +165,13 @@ impl<'g, T> Iterator for Bfs<'g, T> {
     type Item = (NodeIndex, usize);
 
     fn next(&mut self) -> Option<Self::Item> {
+        while let Some((node, _)) = self.queue.front() {
+            if self.visited.contains(node) {
+                self.queue.pop_front();
+            } else {
+                break;
+            }
+        }
         match self.queue.pop_front() {
             None => None,
             Some((node, depth)) => {
@@ -211,4 +236,18 @@ mod test {
 
         assert_eq!(graph.bfs(two).collect::<Vec<_>>(), [(two, 0)]);
     }
+
+    #[test]
+    fn test_bfs_diamond() {
+        let mut graph = Graph::new();
+        let (top, left, right) = (graph.add_node(0), graph.add_node(1), graph.add_node(2));
+        let bottom = graph.add_node(3);
+        graph.add_edge(top, left);
+        graph.add_edge(top, right);
+        graph.add_edge(left, bottom);
+        graph.add_edge(right, bottom);
+        let visits = graph.bfs(top).collect::<Vec<_>>();
+        assert_eq!(visits.len(), 4);
+        assert_eq!(visits[3], (bottom, 2));
+    }
 }
@@ -1,20 +1,42 @@
 //! A virtual machine for the Intcode instruction set.
 
+pub mod ascii;
+pub mod asm;
+pub mod cfg;
+pub mod disasm;
+pub mod fast;
+pub mod history;
 pub mod mem;
+pub mod native;
+pub mod network;
 pub mod op;
+pub mod profile;
+pub mod snapshot;
+pub mod task;
+pub mod threaded;
+pub mod trace;
+pub mod transpile;
 pub mod vm;
 
 #[cfg(test)]
 mod test;
 
+pub use ascii::{AsciiInput, AsciiOutput};
 pub use vm::Input as _;
 pub use vm::Output as _;
 pub use vm::{DefaultInput, DefaultOutput, Machine};
 
+use mem::Word;
+
+use std::borrow::Cow;
+use std::fmt;
+use std::num::ParseIntError;
 use std::str::FromStr;
 
+/// A program, as a list of `W` values. Only `isize` programs can be built
+/// with `From`; programs of other word types are parsed.
 #[derive(Clone, Debug, Eq, PartialEq)]
-pub struct Program(pub Vec<mem::Value>);
+pub struct Program<W = isize>(pub Vec<mem::Value<W>>);
 
 impl<I, T> From<I> for Program
 where
@@ -26,16 +48,116 @@ where
     }
 }
 
-impl FromStr for Program {
-    type Err = !;
+impl<W: Word> fmt::Display for Program<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, value) in self.0.iter().enumerate() {
+            if index > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}", value.0)?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Word> Program<W> {
+    /// Parses a comma-separated program, rejecting empty tokens such as the
+    /// one produced by a doubled or trailing comma.
+    pub fn parse_strict(string: &str) -> Result<Self, ProgramParseError> {
+        Program::parse(string, true)
+    }
+
+    fn parse(string: &str, strict: bool) -> Result<Self, ProgramParseError> {
+        let source = strip_comments(string);
+        let mut values = Vec::new();
+        let mut offset = 0;
+        for raw in source.split(',') {
+            let token = raw.trim();
+            let token_offset = offset + raw.find(|c: char| !c.is_whitespace()).unwrap_or(0);
+            offset += raw.len() + 1;
+            let error = |kind| ProgramParseError {
+                kind,
+                token: token.to_owned(),
+                index: values.len(),
+                offset: token_offset,
+            };
+            if token.is_empty() {
+                if strict {
+                    return Err(error(ProgramParseErrorKind::Empty));
+                }
+                continue;
+            }
+            let value =
+                W::parse_word(token).map_err(|err| error(ProgramParseErrorKind::Invalid(err)))?;
+            values.push(mem::Value(value));
+        }
+        Ok(Program(values))
+    }
+}
+
+/// Blanks out `#` comments, keeping every byte offset of the input intact.
+fn strip_comments(string: &str) -> Cow<'_, str> {
+    if !string.contains('#') {
+        return Cow::Borrowed(string);
+    }
+    let mut stripped = String::with_capacity(string.len());
+    let mut in_comment = false;
+    for c in string.chars() {
+        match c {
+            '#' => in_comment = true,
+            '\n' => in_comment = false,
+            _ => (),
+        }
+        if in_comment {
+            stripped.extend(std::iter::repeat_n(' ', c.len_utf8()));
+        } else {
+            stripped.push(c);
+        }
+    }
+    Cow::Owned(stripped)
+}
+
+impl<W: Word> FromStr for Program<W> {
+    type Err = ProgramParseError;
 
     fn from_str(string: &str) -> Result<Self, Self::Err> {
-        let values = string
-            .split(',')
-            .map(str::parse::<isize>)
-            .filter_map(Result::ok)
-            .map(mem::Value)
-            .collect::<Vec<_>>();
-        Ok(Program::from(values))
+        Program::parse(string, false)
     }
 }
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ProgramParseErrorKind {
+    Empty,
+    Invalid(ParseIntError),
+}
+
+/// An error from parsing a `Program`, locating the offending token both by
+/// its position in the program and by its byte offset in the input.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ProgramParseError {
+    pub kind: ProgramParseErrorKind,
+    pub token: String,
+    /// The index in the program that the token would have had, counting
+    /// only the values before it and not any empty tokens skipped.
+    pub index: usize,
+    pub offset: usize,
+}
+
+impl fmt::Display for ProgramParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            ProgramParseErrorKind::Empty => write!(
+                f,
+                "empty token at index {} (byte offset {})",
+                self.index, self.offset
+            ),
+            ProgramParseErrorKind::Invalid(err) => write!(
+                f,
+                "invalid token {:?} at index {} (byte offset {}): {}",
+                self.token, self.index, self.offset, err
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ProgramParseError {}
@@ -0,0 +1,127 @@
+//! Input and output for programs that talk in ASCII text.
+//!
+//! `AsciiInput` feeds lines of text to a machine one character code at a
+//! time, and `AsciiOutput` collects the codes a machine outputs back into
+//! text. Programs often finish by outputting an answer too large to be a
+//! character, so `AsciiOutput` keeps any value that is not an ASCII code
+//! as a number of its own.
+
+use super::mem::{Value, Word};
+use super::vm::{Input, Output};
+
+use serde_derive::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::mem;
+
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct AsciiInput {
+    pub queue: VecDeque<char>,
+}
+
+impl AsciiInput {
+    /// Queues `line` to be read, followed by a newline.
+    pub fn push_line(&mut self, line: &str) {
+        self.queue.extend(line.chars());
+        self.queue.push_back('\n');
+    }
+}
+
+impl<W: Word> Input<W> for AsciiInput {
+    fn read_input(&mut self) -> Option<Value<W>> {
+        self.queue.pop_front().map(|c| (c as isize).into())
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct AsciiOutput<W = isize> {
+    /// The ASCII characters output so far.
+    pub text: String,
+    /// Every other value output so far.
+    pub values: Vec<Value<W>>,
+}
+
+impl<W> AsciiOutput<W> {
+    /// Returns the text output since it was last taken.
+    pub fn take_text(&mut self) -> String {
+        mem::take(&mut self.text)
+    }
+}
+
+impl<W> Default for AsciiOutput<W> {
+    fn default() -> Self {
+        AsciiOutput {
+            text: String::new(),
+            values: Vec::new(),
+        }
+    }
+}
+
+impl<W: Word> Output<W> for AsciiOutput<W> {
+    fn write_output(&mut self, value: Value<W>) {
+        match value.0.to_isize() {
+            Some(code @ 0..=127) => self.text.push(code as u8 as char),
+            _ => self.values.push(value),
+        }
+    }
+
+    fn output_ready(&self) -> bool {
+        !self.text.is_empty() || !self.values.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::intcode::*;
+
+    /// Prompts for a line, echoes it back in upper case, and outputs its
+    /// length.
+    const SHOUT: &str = "
+                out #62
+                out #32
+        loop:   in c
+                eq c, #10, t
+                jt t, #done
+                lt c, #97, t
+                jt t, #echo
+                add c, #-32, c
+        echo:   out c
+                add n, #1, n
+                jt #1, #loop
+        done:   out #10
+                mul n, #1000, n
+                out n
+                hlt
+        c:      .data 0
+        t:      .data 0
+        n:      .data 0
+    ";
+
+    #[test]
+    fn test_line_round_trip() {
+        let program = asm::assemble(SHOUT).unwrap();
+        let mut machine = Machine::<AsciiInput, AsciiOutput>::new(&program);
+        assert_eq!(machine.run(), vm::Status::Blocked);
+        assert_eq!(machine.output.take_text(), "> ");
+        machine.input.push_line("hello, World");
+        assert_eq!(machine.run(), vm::Status::Halted);
+        assert_eq!(machine.output.take_text(), "HELLO, WORLD\n");
+        assert_eq!(machine.output.values, [12_000.into()]);
+        assert!(machine.output.output_ready());
+    }
+
+    #[test]
+    fn test_non_ascii_values() {
+        let program = Program::from(&[104, 72, 104, -1, 104, 128, 104, 105, 99]);
+        let mut machine = Machine::<AsciiInput, AsciiOutput>::new(&program);
+        assert_eq!(machine.run(), vm::Status::Halted);
+        assert_eq!(machine.output.text, "Hi");
+        assert_eq!(machine.output.values, [(-1).into(), 128.into()]);
+
+        let mut input = AsciiInput::default();
+        input.push_line("é");
+        assert_eq!(input.read_input(), Some(Value(233_isize)));
+        assert_eq!(input.read_input(), Some(Value(10_isize)));
+        assert_eq!(Input::<isize>::read_input(&mut input), None);
+    }
+}
@@ -0,0 +1,367 @@
+//! An assembler for Intcode programs.
+//!
+//! Each line holds an optional `label:`, then an instruction or a directive,
+//! then an optional `;` comment:
+//!
+//! ```text
+//! loop:   in [0]              ; relative mode
+//!         add #1, count, count ; immediate and position mode
+//!         jt #1, #loop
+//!         hlt
+//! count:  .data 0
+//! ```
+//!
+//! Operands are written `#x` for immediate mode, `[x]` for relative mode and
+//! plain `x` for position mode, where `x` is an integer, a label, or a label
+//! plus or minus an integer.
+
+use super::op::{Instruction, Load, Opcode, ParameterMode, Store};
+use super::{mem, Program};
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+
+pub fn assemble(source: &str) -> Result<Program, AsmError> {
+    let mut labels = HashMap::new();
+    let mut items = Vec::new();
+    let mut address = 0;
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        let error = |kind| AsmError { line, kind };
+        let mut text = text.split(';').next().unwrap().trim();
+        while let Some((label, rest)) = split_label(text) {
+            if labels.insert(label, address).is_some() {
+                return Err(error(AsmErrorKind::DuplicateLabel(label.to_owned())));
+            }
+            text = rest;
+        }
+        if text.is_empty() {
+            continue;
+        }
+        let item = parse_item(text).map_err(error)?;
+        address += item.len();
+        items.push((line, item));
+    }
+
+    let mut values = Vec::with_capacity(address);
+    for (line, item) in items {
+        let resolve = |expr: &Expr| {
+            expr.resolve(&labels)
+                .map_err(|kind| AsmError { line, kind })
+        };
+        match item {
+            Item::Instruction(opcode, operands) => {
+                let mut parameters = Vec::with_capacity(operands.len());
+                for operand in &operands {
+                    parameters.push((operand.mode, mem::Value(resolve(&operand.expr)?)));
+                }
+                let instruction =
+                    build(opcode, &parameters).map_err(|kind| AsmError { line, kind })?;
+                values.extend(instruction.encode());
+            }
+            Item::Data(exprs) => {
+                for expr in &exprs {
+                    values.push(mem::Value(resolve(expr)?));
+                }
+            }
+        }
+    }
+    Ok(Program(values))
+}
+
+fn split_label(text: &str) -> Option<(&str, &str)> {
+    let colon = text.find(':')?;
+    let label = &text[..colon];
+    if is_identifier(label) {
+        Some((label, text[colon + 1..].trim_start()))
+    } else {
+        None
+    }
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+enum Item<'s> {
+    Instruction(Opcode, Vec<Operand<'s>>),
+    Data(Vec<Expr<'s>>),
+}
+
+impl<'s> Item<'s> {
+    fn len(&self) -> usize {
+        match self {
+            Item::Instruction(opcode, _) => opcode.len().0 as usize,
+            Item::Data(exprs) => exprs.len(),
+        }
+    }
+}
+
+fn parse_item(text: &str) -> Result<Item<'_>, AsmErrorKind> {
+    let (word, rest) = match text.find(char::is_whitespace) {
+        Some(end) => (&text[..end], text[end..].trim()),
+        None => (text, ""),
+    };
+    let arguments = if rest.is_empty() {
+        vec![]
+    } else {
+        rest.split(',').map(str::trim).collect()
+    };
+    if let Some(directive) = word.strip_prefix('.') {
+        return match directive {
+            "data" => {
+                let exprs = arguments
+                    .into_iter()
+                    .map(Expr::parse)
+                    .collect::<Result<_, _>>()?;
+                Ok(Item::Data(exprs))
+            }
+            _ => Err(AsmErrorKind::UnknownDirective(word.to_owned())),
+        };
+    }
+    let opcode = Opcode::from_mnemonic(word)
+        .ok_or_else(|| AsmErrorKind::UnknownMnemonic(word.to_owned()))?;
+    let expected = opcode.len().0 as usize - 1;
+    if arguments.len() != expected {
+        return Err(AsmErrorKind::OperandCount {
+            expected,
+            found: arguments.len(),
+        });
+    }
+    let operands = arguments
+        .into_iter()
+        .map(Operand::parse)
+        .collect::<Result<_, _>>()?;
+    Ok(Item::Instruction(opcode, operands))
+}
+
+struct Operand<'s> {
+    mode: ParameterMode,
+    expr: Expr<'s>,
+}
+
+impl<'s> Operand<'s> {
+    fn parse(text: &'s str) -> Result<Self, AsmErrorKind> {
+        let (mode, inner) = if let Some(inner) = text.strip_prefix('#') {
+            (ParameterMode::Immediate, inner)
+        } else if let Some(inner) = text.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+            (ParameterMode::Relative, inner)
+        } else {
+            (ParameterMode::Position, text)
+        };
+        let expr =
+            Expr::parse(inner.trim()).map_err(|_| AsmErrorKind::InvalidOperand(text.to_owned()))?;
+        Ok(Operand { mode, expr })
+    }
+}
+
+struct Expr<'s> {
+    label: Option<&'s str>,
+    offset: isize,
+}
+
+impl<'s> Expr<'s> {
+    fn parse(text: &'s str) -> Result<Self, AsmErrorKind> {
+        let invalid = || AsmErrorKind::InvalidOperand(text.to_owned());
+        if let Ok(offset) = text.parse() {
+            return Ok(Expr {
+                label: None,
+                offset,
+            });
+        }
+        let (label, offset) = match text.find(['+', '-']) {
+            Some(sign) => {
+                let offset = text[sign..].replace(' ', "");
+                let offset = offset
+                    .trim_start_matches('+')
+                    .parse()
+                    .map_err(|_| invalid())?;
+                (text[..sign].trim_end(), offset)
+            }
+            None => (text, 0),
+        };
+        if !is_identifier(label) {
+            return Err(invalid());
+        }
+        Ok(Expr {
+            label: Some(label),
+            offset,
+        })
+    }
+
+    fn resolve(&self, labels: &HashMap<&str, usize>) -> Result<isize, AsmErrorKind> {
+        match self.label {
+            None => Ok(self.offset),
+            Some(label) => match labels.get(label) {
+                Some(&address) => Ok(address as isize + self.offset),
+                None => Err(AsmErrorKind::UndefinedLabel(label.to_owned())),
+            },
+        }
+    }
+}
+
+fn build(
+    opcode: Opcode,
+    parameters: &[(ParameterMode, mem::Value)],
+) -> Result<Instruction, AsmErrorKind> {
+    let load = |index: usize| {
+        let (mode, value) = parameters[index];
+        Load::try_from((mode, value)).map_err(|_| AsmErrorKind::NegativeAddress(value.0))
+    };
+    let store = |index: usize| {
+        let (mode, value) = parameters[index];
+        match mode {
+            ParameterMode::Immediate => Err(AsmErrorKind::ImmediateStore),
+            _ => Store::try_from((mode, value)).map_err(|_| AsmErrorKind::NegativeAddress(value.0)),
+        }
+    };
+    let instruction = match opcode {
+        Opcode::Add | Opcode::Multiply | Opcode::LessThan | Opcode::Equals => {
+            Instruction::Arith(opcode, load(0)?, load(1)?, store(2)?)
+        }
+        Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+            Instruction::CondJump(opcode, load(0)?, load(1)?)
+        }
+        Opcode::Input => Instruction::Input(store(0)?),
+        Opcode::Output => Instruction::Output(load(0)?),
+        Opcode::SetRelBase => Instruction::SetRelBase(load(0)?),
+        Opcode::Halt => Instruction::Halt,
+    };
+    Ok(instruction)
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum AsmErrorKind {
+    UnknownMnemonic(String),
+    UnknownDirective(String),
+    OperandCount { expected: usize, found: usize },
+    InvalidOperand(String),
+    ImmediateStore,
+    NegativeAddress(isize),
+    DuplicateLabel(String),
+    UndefinedLabel(String),
+}
+
+/// An error from assembling a program, with the 1-based line it occurred on.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AsmError {
+    pub line: usize,
+    pub kind: AsmErrorKind,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            AsmErrorKind::UnknownMnemonic(word) => write!(f, "unknown mnemonic {:?}", word),
+            AsmErrorKind::UnknownDirective(word) => write!(f, "unknown directive {:?}", word),
+            AsmErrorKind::OperandCount { expected, found } => {
+                write!(f, "expected {} operands, found {}", expected, found)
+            }
+            AsmErrorKind::InvalidOperand(text) => write!(f, "invalid operand {:?}", text),
+            AsmErrorKind::ImmediateStore => write!(f, "write in immediate mode"),
+            AsmErrorKind::NegativeAddress(address) => {
+                write!(f, "negative address {} in position mode", address)
+            }
+            AsmErrorKind::DuplicateLabel(label) => write!(f, "duplicate label {:?}", label),
+            AsmErrorKind::UndefinedLabel(label) => write!(f, "undefined label {:?}", label),
+        }
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::intcode::*;
+
+    #[test]
+    fn test_assemble_instructions() {
+        let source = "
+            add #2, #6, 3
+            mul 4, #3, [4]
+            in [-1]
+            out #-4
+            jt #1, 0
+            jf 4, [3]
+            lt #-4, 3, 12
+            eq 2, 2, 9
+            arb #6
+            hlt
+        ";
+        let expected = Program::from(&[
+            1101, 2, 6, 3, 21002, 4, 3, 4, 203, -1, 104, -4, 105, 1, 0, 2006, 4, 3, 107, -4, 3, 12,
+            8, 2, 2, 9, 109, 6, 99,
+        ]);
+        assert_eq!(assemble(source), Ok(expected));
+    }
+
+    #[test]
+    fn test_assemble_labels_and_data() {
+        let source = "
+            ; prints the input plus one
+            start: in value
+                   add #1, value, value
+                   out value
+                   jf #0, #end
+                   hlt           ; never reached
+            end:   hlt
+            value: .data 0
+            table: .data end, value+1, -7
+        ";
+        let program = assemble(source).unwrap();
+        assert_eq!(
+            program,
+            Program::from(&[3, 13, 101, 1, 13, 13, 4, 13, 1106, 0, 12, 99, 99, 0, 12, 14, -7])
+        );
+
+        let mut machine = Machine::default_io(&program);
+        machine.input.queue.push_back(41.into());
+        assert_eq!(machine.run(), vm::Status::Halted);
+        assert_eq!(machine.output.buffer, [42.into()]);
+    }
+
+    #[test]
+    fn test_assemble_errors() {
+        fn error(source: &str) -> AsmError {
+            assemble(source).unwrap_err()
+        }
+        assert_eq!(
+            error("hlt\nnop"),
+            AsmError {
+                line: 2,
+                kind: AsmErrorKind::UnknownMnemonic("nop".to_owned()),
+            }
+        );
+        assert_eq!(
+            error("add #1, #2").kind,
+            AsmErrorKind::OperandCount {
+                expected: 3,
+                found: 2
+            }
+        );
+        assert_eq!(error("in #3").kind, AsmErrorKind::ImmediateStore);
+        assert_eq!(error("out -3").kind, AsmErrorKind::NegativeAddress(-3));
+        assert_eq!(
+            error("out $3").kind,
+            AsmErrorKind::InvalidOperand("$3".to_owned())
+        );
+        assert_eq!(
+            error("a: hlt\na: hlt").kind,
+            AsmErrorKind::DuplicateLabel("a".to_owned())
+        );
+        assert_eq!(error("\n\njt #1, #nowhere").line, 3);
+        assert_eq!(
+            error(".word 3").kind,
+            AsmErrorKind::UnknownDirective(".word".to_owned())
+        );
+    }
+}
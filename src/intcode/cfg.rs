@@ -0,0 +1,328 @@
+//! A static control-flow graph of an Intcode program.
+//!
+//! Instructions are found with the same linear sweep as `intcode::disasm`,
+//! and split into basic blocks that end at a `CondJump` or `Halt`. Jumps to
+//! an immediate address get edges, unless nothing decodes there, in which
+//! case the block records the target as invalid. Jumps through position or
+//! relative mode cannot be followed statically, so their blocks are marked as
+//! having unknown successors.
+
+use super::disasm::{self, Entry};
+use super::op::{Instruction, Load};
+use super::{mem, Program};
+use crate::graph::{Graph, NodeIndex, Successors};
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BasicBlock {
+    pub entry: mem::Address,
+    pub instructions: Vec<(mem::Address, Instruction)>,
+    pub unknown_successors: bool,
+    /// The immediate target of the block's jump, if no instruction was
+    /// decoded there.
+    pub invalid_target: Option<mem::Value>,
+}
+
+impl BasicBlock {
+    fn new(entry: mem::Address) -> Self {
+        BasicBlock {
+            entry,
+            instructions: vec![],
+            unknown_successors: false,
+            invalid_target: None,
+        }
+    }
+
+    fn end(&self) -> mem::Address {
+        match self.instructions.last() {
+            Some((address, instruction)) => *address + instruction.opcode().len(),
+            None => self.entry,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Cfg {
+    graph: Graph<BasicBlock>,
+    blocks_by_entry: BTreeMap<mem::Address, NodeIndex>,
+    reachable: HashSet<NodeIndex>,
+    loop_heads: HashSet<NodeIndex>,
+}
+
+impl Cfg {
+    pub fn new(program: &Program) -> Self {
+        let instructions = disasm::disassemble(program)
+            .entries
+            .into_iter()
+            .filter_map(|(address, entry)| match entry {
+                Entry::Instruction(instruction) => Some((address, instruction)),
+                Entry::Data(_) => None,
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        let mut leaders = BTreeSet::new();
+        let mut expected = mem::Address(0);
+        for (&address, instruction) in &instructions {
+            let next = address + instruction.opcode().len();
+            if address != expected {
+                leaders.insert(address);
+            }
+            match *instruction {
+                Instruction::CondJump(_, _, target) => {
+                    leaders.insert(next);
+                    if let Some(target) = static_target(target) {
+                        leaders.insert(target);
+                    }
+                }
+                Instruction::Halt => {
+                    leaders.insert(next);
+                }
+                _ => (),
+            }
+            expected = next;
+        }
+        leaders.insert(mem::Address(0));
+
+        let mut graph = Graph::new();
+        let mut blocks_by_entry = BTreeMap::new();
+        let mut current: Option<BasicBlock> = None;
+        for (&address, &instruction) in &instructions {
+            if leaders.contains(&address) {
+                if let Some(block) = current.take() {
+                    blocks_by_entry.insert(block.entry, graph.add_node(block));
+                }
+            }
+            let block = current.get_or_insert_with(|| BasicBlock::new(address));
+            block.instructions.push((address, instruction));
+        }
+        if let Some(block) = current.take() {
+            blocks_by_entry.insert(block.entry, graph.add_node(block));
+        }
+
+        for &node in blocks_by_entry.values() {
+            let block = &graph[node];
+            let mut targets = vec![];
+            let mut unknown_successors = false;
+            let mut invalid_target = None;
+            match block.instructions.last() {
+                Some((_, Instruction::CondJump(opcode, condition, target))) => {
+                    let taken = match condition {
+                        Load::Immediate(value) => Some(opcode.cond_jump_fn()(*value)),
+                        _ => None,
+                    };
+                    if taken != Some(true) {
+                        targets.push(block.end());
+                    }
+                    if taken != Some(false) {
+                        match (static_target(*target), target) {
+                            (Some(address), _) if blocks_by_entry.contains_key(&address) => {
+                                targets.push(address)
+                            }
+                            (_, Load::Immediate(value)) => invalid_target = Some(*value),
+                            _ => unknown_successors = true,
+                        }
+                    }
+                }
+                Some((_, Instruction::Halt)) => (),
+                _ => targets.push(block.end()),
+            }
+            graph[node].unknown_successors = unknown_successors;
+            graph[node].invalid_target = invalid_target;
+            for target in targets {
+                if let Some(&successor) = blocks_by_entry.get(&target) {
+                    graph.add_edge(node, successor);
+                }
+            }
+        }
+
+        let reachable = match blocks_by_entry.get(&mem::Address(0)) {
+            Some(&entry) => graph.bfs(entry).map(|(node, _)| node).collect(),
+            None => HashSet::new(),
+        };
+        let loop_heads = loop_heads(&graph, blocks_by_entry.values().cloned());
+        Cfg {
+            graph,
+            blocks_by_entry,
+            reachable,
+            loop_heads,
+        }
+    }
+
+    pub fn graph(&self) -> &Graph<BasicBlock> {
+        &self.graph
+    }
+
+    /// Returns the blocks in address order.
+    pub fn blocks(&self) -> impl Iterator<Item = NodeIndex> + '_ {
+        self.blocks_by_entry.values().cloned()
+    }
+
+    pub fn entry(&self) -> Option<NodeIndex> {
+        self.block_at(mem::Address(0))
+    }
+
+    /// Returns the block starting at `address`.
+    pub fn block_at(&self, address: mem::Address) -> Option<NodeIndex> {
+        self.blocks_by_entry.get(&address).cloned()
+    }
+
+    pub fn successors(&self, block: NodeIndex) -> Successors<'_, BasicBlock> {
+        self.graph.successors(block)
+    }
+
+    pub fn is_reachable(&self, block: NodeIndex) -> bool {
+        self.reachable.contains(&block)
+    }
+
+    pub fn is_loop_head(&self, block: NodeIndex) -> bool {
+        self.loop_heads.contains(&block)
+    }
+
+    pub fn reachable(&self) -> impl Iterator<Item = NodeIndex> + '_ {
+        self.blocks().filter(move |&block| self.is_reachable(block))
+    }
+
+    pub fn unreachable(&self) -> impl Iterator<Item = NodeIndex> + '_ {
+        self.blocks()
+            .filter(move |&block| !self.is_reachable(block))
+    }
+}
+
+impl std::ops::Index<NodeIndex> for Cfg {
+    type Output = BasicBlock;
+
+    fn index(&self, index: NodeIndex) -> &Self::Output {
+        &self.graph[index]
+    }
+}
+
+fn static_target(target: Load) -> Option<mem::Address> {
+    match target {
+        Load::Immediate(value) if value.0 >= 0 => Some(mem::Address(value.0 as usize)),
+        _ => None,
+    }
+}
+
+/// Finds the targets of back edges, meaning edges to a block that is still
+/// being explored by a depth-first search from `roots`.
+fn loop_heads<T>(graph: &Graph<T>, roots: impl Iterator<Item = NodeIndex>) -> HashSet<NodeIndex> {
+    let mut loop_heads = HashSet::new();
+    let mut visited = HashSet::new();
+    let mut on_stack = HashSet::new();
+    for root in roots {
+        if !visited.insert(root) {
+            continue;
+        }
+        on_stack.insert(root);
+        let mut stack = vec![(root, graph.successors(root))];
+        while let Some((node, successors)) = stack.last_mut() {
+            match successors.next() {
+                Some(next) if on_stack.contains(&next) => {
+                    loop_heads.insert(next);
+                }
+                Some(next) => {
+                    if visited.insert(next) {
+                        on_stack.insert(next);
+                        stack.push((next, graph.successors(next)));
+                    }
+                }
+                None => {
+                    on_stack.remove(node);
+                    stack.pop();
+                }
+            }
+        }
+    }
+    loop_heads
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::intcode::asm;
+
+    fn entries(cfg: &Cfg, blocks: impl Iterator<Item = NodeIndex>) -> Vec<usize> {
+        blocks.map(|block| cfg[block].entry.0).collect()
+    }
+
+    fn successors(cfg: &Cfg, address: usize) -> BTreeSet<usize> {
+        let block = cfg.block_at(mem::Address(address)).unwrap();
+        cfg.successors(block)
+            .map(|next| cfg[next].entry.0)
+            .collect()
+    }
+
+    #[test]
+    fn test_blocks_and_edges() {
+        let program = asm::assemble(
+            "
+                    in 100           ; 0
+            loop:   add 100, #-1, 100 ; 2
+                    out 100          ; 6
+                    jt 100, #loop    ; 8
+                    jf #0, #done     ; 11
+                    hlt              ; 14
+            done:   jt 101, 102      ; 15
+                    hlt              ; 18
+            ",
+        )
+        .unwrap();
+        let cfg = Cfg::new(&program);
+        assert_eq!(entries(&cfg, cfg.blocks()), [0, 2, 11, 14, 15, 18]);
+        assert_eq!(successors(&cfg, 0), [2].iter().cloned().collect());
+        assert_eq!(successors(&cfg, 2), [2, 11].iter().cloned().collect());
+        assert_eq!(successors(&cfg, 11), [15].iter().cloned().collect());
+        assert_eq!(successors(&cfg, 15), [18].iter().cloned().collect());
+        assert!(successors(&cfg, 18).is_empty());
+
+        let done = cfg.block_at(mem::Address(15)).unwrap();
+        assert!(cfg[done].unknown_successors);
+        assert_eq!(cfg[done].instructions.len(), 1);
+
+        let body = cfg.block_at(mem::Address(2)).unwrap();
+        assert_eq!(cfg[body].instructions.len(), 3);
+        assert!(!cfg[body].unknown_successors);
+        assert!(cfg.is_loop_head(body));
+        assert_eq!(
+            entries(&cfg, cfg.blocks().filter(|&b| cfg.is_loop_head(b))),
+            [2]
+        );
+
+        assert_eq!(entries(&cfg, cfg.reachable()), [0, 2, 11, 15, 18]);
+        assert_eq!(entries(&cfg, cfg.unreachable()), [14]);
+    }
+
+    #[test]
+    fn test_invalid_targets() {
+        let program = asm::assemble(
+            "
+                    jt 100, #data    ; 0
+                    jt 100, #-1      ; 3
+                    jt 100, #7       ; 6
+                    hlt              ; 9
+            data:   .data 42         ; 10
+            ",
+        )
+        .unwrap();
+        let cfg = Cfg::new(&program);
+        let invalid_targets = cfg
+            .blocks()
+            .map(|block| cfg[block].invalid_target.map(|value| value.0))
+            .collect::<Vec<_>>();
+        assert_eq!(invalid_targets, [Some(10), Some(-1), Some(7), None]);
+        assert!(cfg.blocks().all(|block| !cfg[block].unknown_successors));
+        assert_eq!(successors(&cfg, 0), [3].iter().cloned().collect());
+    }
+
+    #[test]
+    fn test_puzzle_program_reachability() {
+        let program = include_str!("../../input/day09.in")
+            .parse::<Program>()
+            .unwrap();
+        let cfg = Cfg::new(&program);
+        let entry = cfg.entry().unwrap();
+        assert!(cfg.is_reachable(entry));
+        assert!(cfg.reachable().count() > 1);
+    }
+}
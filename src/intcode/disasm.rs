@@ -0,0 +1,208 @@
+//! A disassembler producing annotated listings of Intcode programs.
+//!
+//! Listings are written in the syntax accepted by `intcode::asm`, with each
+//! line's address and raw values in a trailing comment, so assembling a
+//! listing gives back the program it was produced from.
+
+use super::op::{Instruction, Load};
+use super::{mem, Program};
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+const DATA_PER_LINE: usize = 8;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Entry {
+    Instruction(Instruction),
+    Data(Vec<mem::Value>),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Listing {
+    pub entries: Vec<(mem::Address, Entry)>,
+    pub labels: BTreeSet<mem::Address>,
+}
+
+/// Disassembles `program` with a linear sweep. Values that do not decode,
+/// or that decode to an instruction encoded differently (such as one with
+/// mode digits for parameters it does not have), are listed as `.data`.
+pub fn disassemble(program: &Program) -> Listing {
+    let memory: mem::Memory = mem::Memory::from(program);
+    let mut entries: Vec<(mem::Address, Entry)> = Vec::new();
+    let mut address = 0;
+    while address < program.0.len() {
+        let start = mem::Address(address);
+        let instruction = memory.read_instruction(start).ok().filter(|instruction| {
+            let encoded = instruction.encode();
+            program.0[address..].starts_with(&encoded)
+        });
+        match instruction {
+            Some(instruction) => {
+                entries.push((start, Entry::Instruction(instruction)));
+                address += instruction.opcode().len().0 as usize;
+            }
+            None => {
+                match entries.last_mut() {
+                    Some((_, Entry::Data(values))) => values.push(program.0[address]),
+                    _ => entries.push((start, Entry::Data(vec![program.0[address]]))),
+                }
+                address += 1;
+            }
+        }
+    }
+
+    let targets = entries
+        .iter()
+        .filter_map(|(_, entry)| match entry {
+            Entry::Instruction(Instruction::CondJump(_, _, Load::Immediate(target))) => {
+                Some(target.0)
+            }
+            _ => None,
+        })
+        .filter(|&target| 0 <= target && (target as usize) < program.0.len())
+        .map(|target| mem::Address(target as usize))
+        .collect::<BTreeSet<_>>();
+
+    let mut listing = Listing {
+        entries: Vec::with_capacity(entries.len()),
+        labels: BTreeSet::new(),
+    };
+    for (start, entry) in entries {
+        match entry {
+            Entry::Instruction(_) => {
+                if targets.contains(&start) {
+                    listing.labels.insert(start);
+                }
+                listing.entries.push((start, entry));
+            }
+            Entry::Data(values) => {
+                let mut line_start = 0;
+                for index in 0..values.len() {
+                    let address = mem::Address(start.0 + index);
+                    let labelled = targets.contains(&address);
+                    if labelled {
+                        listing.labels.insert(address);
+                    }
+                    if index > line_start && (labelled || index - line_start == DATA_PER_LINE) {
+                        let line = values[line_start..index].to_vec();
+                        let line_address = mem::Address(start.0 + line_start);
+                        listing.entries.push((line_address, Entry::Data(line)));
+                        line_start = index;
+                    }
+                }
+                let line = values[line_start..].to_vec();
+                let line_address = mem::Address(start.0 + line_start);
+                listing.entries.push((line_address, Entry::Data(line)));
+            }
+        }
+    }
+    listing
+}
+
+impl Listing {
+    fn is_label(&self, value: mem::Value) -> bool {
+        value.0 >= 0 && self.labels.contains(&mem::Address(value.0 as usize))
+    }
+
+    fn code(&self, entry: &Entry) -> String {
+        match entry {
+            Entry::Instruction(Instruction::CondJump(opcode, x, Load::Immediate(target)))
+                if self.is_label(*target) =>
+            {
+                format!("{} {}, #L{}", opcode.mnemonic(), x, target.0)
+            }
+            Entry::Instruction(instruction) => instruction.to_string(),
+            Entry::Data(values) => {
+                let values = values.iter().map(|value| value.0.to_string());
+                format!(".data {}", values.collect::<Vec<_>>().join(", "))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (address, entry) in &self.entries {
+            let label = if self.labels.contains(address) {
+                format!("L{}:", address.0)
+            } else {
+                String::new()
+            };
+            let raw = match entry {
+                Entry::Instruction(instruction) => instruction.encode(),
+                Entry::Data(values) => values.clone(),
+            };
+            let raw = raw.iter().map(|value| value.0.to_string());
+            writeln!(
+                f,
+                "{:<8}{:<32} ; {:04}: {}",
+                label,
+                self.code(entry),
+                address.0,
+                raw.collect::<Vec<_>>().join(",")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::intcode::asm;
+
+    use quickcheck_macros::quickcheck;
+
+    #[test]
+    fn test_disassemble() {
+        let program = Program::from(&[
+            3, 12, 1008, 12, 8, 13, 1005, 13, 11, 104, 0, 99, -1, 7, 20101,
+        ]);
+        let listing = disassemble(&program);
+        assert_eq!(
+            listing.to_string(),
+            "        in 12                            ; 0000: 3,12
+        eq 12, #8, 13                    ; 0002: 1008,12,8,13
+        jt 13, #L11                      ; 0006: 1005,13,11
+        out #0                           ; 0009: 104,0
+L11:    hlt                              ; 0011: 99
+        .data -1, 7, 20101               ; 0012: -1,7,20101
+"
+        );
+    }
+
+    #[test]
+    fn test_jump_into_data() {
+        let program = Program::from(&[1105, 1, 4, 99, -1, -2]);
+        let listing = disassemble(&program);
+        assert_eq!(listing.labels, [mem::Address(4)].iter().cloned().collect());
+        assert_eq!(
+            listing.entries[2],
+            (mem::Address(4), Entry::Data(vec![(-1).into(), (-2).into()]))
+        );
+        assert_eq!(asm::assemble(&listing.to_string()), Ok(program));
+    }
+
+    #[test]
+    fn test_round_trip_puzzle_programs() {
+        let inputs = [
+            include_str!("../../input/day05.in"),
+            include_str!("../../input/day09.in"),
+            include_str!("../../input/day11.in"),
+            include_str!("../../input/day13.in"),
+        ];
+        for input in inputs.iter() {
+            let program = input.parse::<Program>().unwrap();
+            let listing = disassemble(&program).to_string();
+            assert_eq!(asm::assemble(&listing), Ok(program));
+        }
+    }
+
+    #[quickcheck]
+    fn prop_disassemble_round_trip(values: Vec<isize>) -> bool {
+        let program = Program::from(values);
+        let listing = disassemble(&program).to_string();
+        asm::assemble(&listing) == Ok(program)
+    }
+}
@@ -0,0 +1,264 @@
+//! A faster engine for running a `vm::Machine`, selected with
+//! `vm::Engine::Fast`.
+//!
+//! The first time the engine reaches an instruction it compiles it to an
+//! `Op`, with its operand modes resolved and the common shapes (arithmetic
+//! that stores to a fixed address, jumps to a fixed target) given variants of
+//! their own, and keeps it in memory for next time. Whatever an op cannot
+//! finish without faulting is handed to `Machine::step` before any state has
+//! changed, so the engine behaves exactly like the reference interpreter.
+//!
+//! Writing to memory under a compiled instruction marks it as overwritten,
+//! and from then on the reference interpreter runs whatever is there.
+
+use super::mem::{Address, Memory, MemoryBackend, Overflow, PageTable, Value, Word};
+use super::op::{Instruction, Load, Opcode, Store};
+use super::trace::Tracer;
+use super::vm::{Input, Machine, Output, Status};
+
+use std::convert::TryFrom;
+use std::fmt;
+
+#[derive(Clone, Debug)]
+enum Op<W> {
+    /// An arithmetic instruction that stores to a position.
+    ArithTo(Opcode, Load<W>, Load<W>, Address),
+    Arith(Opcode, Load<W>, Load<W>, Store),
+    /// A jump to an immediate target, taken if the value is `true` or
+    /// `false` as the `bool` says.
+    JumpTo(bool, Load<W>, Address),
+    Jump(bool, Load<W>, Load<W>),
+    Input(Store),
+    Output(Load<W>),
+    SetRelBase(Load<W>),
+    Halt,
+}
+
+impl<W: Word> Op<W> {
+    fn compile(instruction: Instruction<W>) -> Self {
+        match instruction {
+            Instruction::Arith(opcode, lhs, rhs, Store::Position(address)) => {
+                Op::ArithTo(opcode, lhs, rhs, address)
+            }
+            Instruction::Arith(opcode, lhs, rhs, store) => Op::Arith(opcode, lhs, rhs, store),
+            Instruction::CondJump(opcode, x, target) => {
+                let when = opcode == Opcode::JumpIfTrue;
+                let address = match &target {
+                    Load::Immediate(value) => Address::try_from(value.clone()).ok(),
+                    _ => None,
+                };
+                match address {
+                    Some(address) => Op::JumpTo(when, x, address),
+                    None => Op::Jump(when, x, target),
+                }
+            }
+            Instruction::Input(store) => Op::Input(store),
+            Instruction::Output(load) => Op::Output(load),
+            Instruction::SetRelBase(load) => Op::SetRelBase(load),
+            Instruction::Halt => Op::Halt,
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Op::ArithTo(..) | Op::Arith(..) => 4,
+            Op::JumpTo(..) | Op::Jump(..) => 3,
+            Op::Input(_) | Op::Output(_) | Op::SetRelBase(_) => 2,
+            Op::Halt => 1,
+        }
+    }
+}
+
+fn arith<W: Word>(
+    opcode: Opcode,
+    lhs: Value<W>,
+    rhs: Value<W>,
+    overflow: Overflow,
+) -> Option<Value<W>> {
+    match opcode {
+        Opcode::Add => lhs.add_with(rhs, overflow),
+        Opcode::Multiply => lhs.mul_with(rhs, overflow),
+        Opcode::LessThan => Some((lhs < rhs).into()),
+        _ => Some((lhs == rhs).into()),
+    }
+}
+
+/// Loads like `Memory::load`, without taking the `Load` from its op.
+fn load<B: MemoryBackend>(memory: &Memory<B>, load: &Load<B::Word>) -> Option<Value<B::Word>> {
+    match load {
+        Load::Immediate(value) => Some(value.clone()),
+        Load::Position(address) => memory.load(Load::Position(*address)).ok(),
+        Load::Relative(offset) => memory.load(Load::Relative(*offset)).ok(),
+    }
+}
+
+#[derive(Clone)]
+enum Slot<W> {
+    Compiled(Op<W>),
+    Overwritten,
+}
+
+/// The ops compiled from a memory's instructions, by address.
+#[derive(Clone, Default)]
+pub(crate) struct Code<W> {
+    slots: PageTable<Slot<W>>,
+}
+
+impl<W: Word> Code<W> {
+    fn get(&self, address: Address) -> Option<&Slot<W>> {
+        self.slots.get(address)
+    }
+
+    fn insert(&mut self, address: Address, op: Op<W>) {
+        self.slots.insert(address, op.len(), Slot::Compiled(op));
+    }
+
+    /// Marks the ops that `address` is part of as overwritten.
+    pub(crate) fn invalidate(&mut self, address: Address) {
+        self.slots.overlapping(address, |start, slot| {
+            if let Some(Slot::Compiled(op)) = slot {
+                if start.0 + op.len() > address.0 {
+                    *slot = Some(Slot::Overwritten);
+                }
+            }
+        });
+    }
+
+    pub(crate) fn truncate(&mut self, len: usize) {
+        self.slots.truncate(len);
+    }
+}
+
+impl<W> fmt::Debug for Code<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Code").finish_non_exhaustive()
+    }
+}
+
+impl<I, O, T, B> Machine<I, O, T, B>
+where
+    I: Input<B::Word>,
+    O: Output<B::Word>,
+    T: Tracer<B::Word>,
+    B: MemoryBackend,
+{
+    /// Runs like `run`, or like `run_with_fuel` if given `fuel`, on compiled
+    /// code.
+    pub(crate) fn run_fast(&mut self, mut fuel: Option<u64>) -> Status<B::Word> {
+        if let Status::Halted | Status::Faulted(_) = self.status {
+            return self.status.clone();
+        }
+        self.status = Status::Ready;
+        loop {
+            if let Some(fuel) = &mut fuel {
+                if *fuel == 0 {
+                    self.status = Status::OutOfFuel;
+                    return Status::OutOfFuel;
+                }
+                *fuel -= 1;
+            }
+            let status = match self.execute_compiled() {
+                Some(status) => status,
+                None => self.step(),
+            };
+            if status != Status::Ready {
+                return status;
+            }
+        }
+    }
+
+    /// Compiles the instruction at the instruction pointer, or returns
+    /// `false` if it does not decode.
+    fn compile(&mut self) -> bool {
+        match self.memory.read_instruction(self.ins_ptr) {
+            Ok(instruction) => {
+                let op = Op::compile(instruction);
+                self.memory.code.insert(self.ins_ptr, op);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Executes the op at the instruction pointer, compiling it first if it
+    /// has not been, or returns `None` without changing anything if it was
+    /// overwritten, does not decode or would fault.
+    fn execute_compiled(&mut self) -> Option<Status<B::Word>> {
+        let op = match self.memory.code.get(self.ins_ptr) {
+            Some(Slot::Compiled(op)) => op,
+            Some(Slot::Overwritten) => return None,
+            None => {
+                return if self.compile() {
+                    self.execute_compiled()
+                } else {
+                    None
+                }
+            }
+        };
+        let memory = &self.memory;
+        let mut next = Address(self.ins_ptr.0 + op.len());
+        // The op is borrowed from memory, so stores wait until it is done.
+        let mut store = None;
+        match op {
+            Op::ArithTo(opcode, lhs, rhs, address) => {
+                let lhs = load(memory, lhs)?;
+                let rhs = load(memory, rhs)?;
+                let result = arith(*opcode, lhs, rhs, self.overflow)?;
+                if address.0 >= memory.limit {
+                    return None;
+                }
+                store = Some((*address, result));
+            }
+            Op::Arith(opcode, lhs, rhs, to) => {
+                let lhs = load(memory, lhs)?;
+                let rhs = load(memory, rhs)?;
+                let result = arith(*opcode, lhs, rhs, self.overflow)?;
+                store = Some((memory.store_address(*to).ok()?, result));
+            }
+            Op::JumpTo(when, x, address) => {
+                if bool::from(load(memory, x)?) == *when {
+                    if address.0 >= memory.len() {
+                        return None;
+                    }
+                    next = *address;
+                }
+            }
+            Op::Jump(when, x, target) => {
+                let x = load(memory, x)?;
+                let target = load(memory, target)?;
+                if bool::from(x) == *when {
+                    next = Address::try_from(target).ok()?;
+                    if next.0 >= memory.len() {
+                        return None;
+                    }
+                }
+            }
+            Op::Input(to) => {
+                let address = memory.store_address(*to).ok()?;
+                match self.input.read_input() {
+                    Some(input) => store = Some((address, input)),
+                    None => {
+                        self.status = Status::Blocked;
+                        return Some(Status::Blocked);
+                    }
+                }
+            }
+            Op::Output(value) => self.output.write_output(load(memory, value)?),
+            Op::SetRelBase(value) => {
+                let value = load(memory, value)?;
+                self.memory.rel_base = memory.rel_base.add_with(value, self.overflow)?;
+            }
+            Op::Halt => {
+                self.retired += 1;
+                self.status = Status::Halted;
+                return Some(Status::Halted);
+            }
+        }
+        if let Some((address, value)) = store {
+            self.memory[address] = value;
+        }
+        self.ins_ptr = next;
+        self.retired += 1;
+        Some(Status::Ready)
+    }
+}
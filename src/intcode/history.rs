@@ -0,0 +1,350 @@
+//! Reverse execution for `vm::Machine`.
+//!
+//! A `History` is a tracer that keeps an undo log of every instruction the
+//! machine executes: the instruction pointer, the memory it overwrote, the
+//! relative base it replaced, and any input it consumed or output it
+//! produced. Machines whose tracer records a history, and whose input and
+//! output can be rewound, can then `step_back` through it.
+//!
+//! Changes made to the machine by hand, through `poke`, `set_rel_base` and
+//! `set_ins_ptr`, are logged too, so that stepping back undoes them in order
+//! with the instructions around them.
+//!
+//! The log is bounded by a memory limit; once it is full, the oldest entries
+//! are discarded, so a machine can only be rewound so far.
+
+use super::mem;
+use super::op::Instruction;
+use super::trace::{Completion, Trace, Tracer};
+use super::vm::{DefaultInput, DefaultOutput, Input, Machine, Output, Status};
+
+use std::collections::VecDeque;
+use std::mem::size_of;
+
+/// The default memory limit of a `History`, in bytes.
+pub const DEFAULT_MEMORY_LIMIT: usize = 16 << 20;
+
+/// An input that can be given back a value it has already read.
+pub trait RewindInput: Input {
+    fn unread_input(&mut self, value: mem::Value);
+}
+
+/// An output that can take back the last value written to it.
+pub trait RewindOutput: Output {
+    fn unwrite_output(&mut self) -> Option<mem::Value>;
+}
+
+impl RewindInput for DefaultInput {
+    fn unread_input(&mut self, value: mem::Value) {
+        self.queue.push_front(value);
+    }
+}
+
+impl RewindOutput for DefaultOutput {
+    fn unwrite_output(&mut self) -> Option<mem::Value> {
+        self.buffer.pop()
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum UndoIo {
+    Read(mem::Value),
+    Wrote,
+}
+
+/// Everything needed to reverse one instruction, or one edit made by hand.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+struct Undo {
+    ins_ptr: mem::Address,
+    store: Option<(mem::Address, mem::Value)>,
+    rel_base: Option<mem::Offset>,
+    io: Option<UndoIo>,
+    memory_len: usize,
+    edit: bool,
+}
+
+impl From<&Trace> for Undo {
+    fn from(trace: &Trace) -> Self {
+        let io = match (trace.instruction, trace.store) {
+            (Instruction::Input(_), Some(store)) => Some(UndoIo::Read(store.value)),
+            (Instruction::Output(_), _) => Some(UndoIo::Wrote),
+            _ => None,
+        };
+        Undo {
+            ins_ptr: trace.ins_ptr,
+            store: trace.store.map(|store| (store.address, store.previous)),
+            rel_base: trace.rel_base.map(|(old, _)| old),
+            io,
+            memory_len: trace.memory_len,
+            edit: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct History {
+    entries: VecDeque<Undo>,
+    capacity: usize,
+}
+
+impl History {
+    /// Creates a history that uses at most about `memory_limit` bytes, and
+    /// always has room for at least one entry.
+    pub fn new(memory_limit: usize) -> Self {
+        History {
+            entries: VecDeque::new(),
+            capacity: (memory_limit / size_of::<Undo>()).max(1),
+        }
+    }
+
+    /// The number of steps that can currently be undone.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The most steps this history will hold.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    fn push(&mut self, undo: Undo) {
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(undo);
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History::new(DEFAULT_MEMORY_LIMIT)
+    }
+}
+
+impl Tracer for History {
+    fn trace(&mut self, trace: &Trace) {
+        if trace.completion != Completion::Retired {
+            return;
+        }
+        self.push(trace.into());
+    }
+}
+
+impl AsMut<History> for History {
+    fn as_mut(&mut self) -> &mut History {
+        self
+    }
+}
+
+impl<B: Tracer> AsMut<History> for (History, B) {
+    fn as_mut(&mut self) -> &mut History {
+        &mut self.0
+    }
+}
+
+impl<I, O, T, B> Machine<I, O, T, B>
+where
+    I: RewindInput,
+    O: RewindOutput,
+    T: Tracer + AsMut<History>,
+    B: mem::MemoryBackend<Word = isize>,
+{
+    /// Undoes the most recently executed instruction, leaving the machine
+    /// ready to execute it again, or the most recent edit. Returns `false`,
+    /// without changing state, if there is no history left.
+    pub fn step_back(&mut self) -> bool {
+        let undo = match self.tracer.as_mut().entries.pop_back() {
+            Some(undo) => undo,
+            None => return false,
+        };
+        if let Some((address, previous)) = undo.store {
+            self.memory[address] = previous;
+        }
+        self.memory.truncate(undo.memory_len);
+        if let Some(rel_base) = undo.rel_base {
+            self.memory.rel_base = rel_base;
+        }
+        match undo.io {
+            Some(UndoIo::Read(value)) => self.input.unread_input(value),
+            Some(UndoIo::Wrote) => {
+                self.output.unwrite_output();
+            }
+            None => (),
+        }
+        self.ins_ptr = undo.ins_ptr;
+        if !undo.edit {
+            self.retired -= 1;
+            self.status = Status::Ready;
+        }
+        true
+    }
+
+    /// Steps back at least once, and then until the instruction pointer is
+    /// at `address`. Returns `false` if the history runs out first, leaving
+    /// the machine at the oldest state it recorded.
+    pub fn run_back_to(&mut self, address: mem::Address) -> bool {
+        loop {
+            if !self.step_back() {
+                return false;
+            }
+            if self.ins_ptr == address {
+                return true;
+            }
+        }
+    }
+
+    fn record_edit(
+        &mut self,
+        store: Option<(mem::Address, mem::Value)>,
+        rel_base: Option<mem::Offset>,
+    ) {
+        let undo = Undo {
+            ins_ptr: self.ins_ptr,
+            store,
+            rel_base,
+            io: None,
+            memory_len: self.memory.len(),
+            edit: true,
+        };
+        self.tracer.as_mut().push(undo);
+    }
+
+    /// Writes `value` to memory at `address`, as a step that can be undone.
+    pub fn poke(&mut self, address: mem::Address, value: mem::Value) {
+        self.record_edit(Some((address, self.memory[address])), None);
+        self.memory[address] = value;
+    }
+
+    /// Sets the relative base, as a step that can be undone.
+    pub fn set_rel_base(&mut self, rel_base: mem::Offset) {
+        self.record_edit(None, Some(self.memory.rel_base));
+        self.memory.rel_base = rel_base;
+    }
+
+    /// Moves the instruction pointer, as a step that can be undone.
+    pub fn set_ins_ptr(&mut self, address: mem::Address) {
+        self.record_edit(None, None);
+        self.ins_ptr = address;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::intcode::*;
+
+    type Rewindable = Machine<DefaultInput, DefaultOutput, History>;
+
+    fn snapshot(machine: &Rewindable) -> (Vec<mem::Value>, mem::Address, mem::Offset) {
+        let len = machine.memory.len();
+        let memory = (0..len).map(|address| machine.memory[mem::Address(address)]);
+        (memory.collect(), machine.ins_ptr, machine.memory.rel_base)
+    }
+
+    #[test]
+    fn test_step_back_restores_every_state() {
+        let program = include_str!("../../input/day09.in")
+            .parse::<Program>()
+            .unwrap();
+        let mut machine = Rewindable::new(&program);
+        machine.input.queue.push_back(1.into());
+        let mut states = vec![];
+        while machine.status == vm::Status::Ready {
+            states.push((
+                snapshot(&machine),
+                machine.input.clone(),
+                machine.output.clone(),
+            ));
+            machine.step();
+        }
+        assert_eq!(machine.status, vm::Status::Halted);
+        assert_eq!(machine.tracer.len(), states.len());
+
+        while let Some((state, input, output)) = states.pop() {
+            assert!(machine.step_back());
+            assert_eq!(snapshot(&machine), state);
+            assert_eq!(machine.input, input);
+            assert_eq!(machine.output, output);
+        }
+        assert!(!machine.step_back());
+        assert_eq!(machine.retired, 0);
+        assert_eq!(machine.run(), vm::Status::Halted);
+    }
+
+    #[test]
+    fn test_run_back_to() {
+        // Outputs 3, 2, 1 from a countdown loop.
+        let program = Program::from(&[4, 12, 1001, 12, -1, 12, 1005, 12, 0, 99, 0, 0, 3]);
+        let mut machine = Rewindable::new(&program);
+        assert_eq!(machine.run(), vm::Status::Halted);
+        assert_eq!(machine.output.buffer, [3.into(), 2.into(), 1.into()]);
+
+        assert!(machine.run_back_to(mem::Address(0)));
+        assert_eq!(machine.output.buffer, [3.into(), 2.into()]);
+        assert_eq!(machine.memory[mem::Address(12)], 1.into());
+        assert!(machine.run_back_to(mem::Address(0)));
+        assert!(machine.run_back_to(mem::Address(0)));
+        assert!(machine.output.buffer.is_empty());
+        assert!(!machine.run_back_to(mem::Address(0)));
+        assert_eq!(machine.run(), vm::Status::Halted);
+        assert_eq!(machine.output.buffer, [3.into(), 2.into(), 1.into()]);
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let program = Program::from(&[4, 12, 1001, 12, -1, 12, 1005, 12, 0, 99, 0, 0, 3]);
+        let history = History::new(4 * size_of::<Undo>());
+        assert_eq!(history.capacity(), 4);
+        let mut machine = Machine::default_io(&program).with_tracer(history);
+        machine.run();
+        assert_eq!(machine.tracer.len(), 4);
+        assert!(machine.run_back_to(mem::Address(0)));
+        assert!(!machine.step_back());
+        assert_eq!(machine.output.buffer, [3.into(), 2.into()]);
+        assert_eq!(machine.memory[mem::Address(12)], 1.into());
+    }
+
+    #[test]
+    fn test_edits_are_undone() {
+        let program = Program::from(&[109, 3, 204, 0, 99]);
+        let mut machine = Rewindable::new(&program);
+        machine.step();
+        let before = snapshot(&machine);
+        machine.poke(mem::Address(10), 7.into());
+        machine.set_rel_base(mem::Offset(10));
+        machine.set_ins_ptr(mem::Address(2));
+        assert_eq!(machine.run(), vm::Status::Halted);
+        assert_eq!(machine.output.buffer, [7.into()]);
+
+        assert!(machine.run_back_to(mem::Address(2)));
+        assert!(machine.output.buffer.is_empty());
+        assert_eq!(machine.retired, 1);
+        assert!(machine.step_back());
+        assert!(machine.step_back());
+        assert!(machine.step_back());
+        assert_eq!(snapshot(&machine), before);
+        assert_eq!(machine.retired, 1);
+        assert_eq!(machine.tracer.len(), 1);
+    }
+
+    #[test]
+    fn test_store_past_end_is_undone() {
+        let program = Program::from(&[3, 20, 99]);
+        let mut machine = Rewindable::new(&program);
+        machine.input.queue.push_back(5.into());
+        machine.run();
+        assert_eq!(machine.memory.len(), 21);
+        assert!(machine.step_back());
+        assert!(machine.step_back());
+        assert_eq!(machine.memory.len(), 3);
+        assert_eq!(machine.input.queue, [mem::Value(5)]);
+    }
+}
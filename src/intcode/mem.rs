@@ -1,52 +1,289 @@
-use super::{op, Program};
+use super::{fast, op, Program};
 
+use num::{BigInt, Signed, ToPrimitive};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
+use std::fmt;
 use std::iter;
+use std::num::ParseIntError;
+use std::sync::Arc;
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
-pub struct Value(pub isize);
+/// The integer type a machine computes with. Addresses, offsets and opcodes
+/// are still `isize`-sized, so a word only needs to convert to an `isize`
+/// when it is used as one of those.
+pub trait Word: Clone + Default + fmt::Debug + fmt::Display + Eq + Ord {
+    fn from_isize(i: isize) -> Self;
 
-impl std::ops::Add for Value {
-    type Output = Value;
+    /// Returns the word as an `isize`, or `None` if it does not fit.
+    fn to_isize(&self) -> Option<isize>;
+
+    fn to_bigint(&self) -> BigInt;
+
+    fn is_negative(&self) -> bool;
+
+    fn parse_word(token: &str) -> Result<Self, ParseIntError>;
+
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+    fn wrapping_add(&self, other: &Self) -> Self;
+    fn wrapping_mul(&self, other: &Self) -> Self;
+    fn saturating_add(&self, other: &Self) -> Self;
+    fn saturating_mul(&self, other: &Self) -> Self;
+
+    /// Returns the word as an `isize`, saturating at its bounds.
+    fn saturating_to_isize(&self) -> isize {
+        match self.to_isize() {
+            Some(i) => i,
+            None if self.is_negative() => isize::MIN,
+            None => isize::MAX,
+        }
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl Word for isize {
+    fn from_isize(i: isize) -> Self {
+        i
+    }
+
+    fn to_isize(&self) -> Option<isize> {
+        Some(*self)
+    }
+
+    fn to_bigint(&self) -> BigInt {
+        BigInt::from(*self)
+    }
+
+    fn is_negative(&self) -> bool {
+        *self < 0
+    }
+
+    fn parse_word(token: &str) -> Result<Self, ParseIntError> {
+        token.parse()
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        isize::checked_add(*self, *other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        isize::checked_mul(*self, *other)
+    }
+
+    fn wrapping_add(&self, other: &Self) -> Self {
+        isize::wrapping_add(*self, *other)
+    }
+
+    fn wrapping_mul(&self, other: &Self) -> Self {
+        isize::wrapping_mul(*self, *other)
+    }
+
+    fn saturating_add(&self, other: &Self) -> Self {
+        isize::saturating_add(*self, *other)
+    }
+
+    fn saturating_mul(&self, other: &Self) -> Self {
+        isize::saturating_mul(*self, *other)
+    }
+}
+
+macro_rules! impl_word {
+    ($($t:ident),*) => {$(
+        impl Word for $t {
+            fn from_isize(i: isize) -> Self {
+                i as $t
+            }
+
+            fn to_isize(&self) -> Option<isize> {
+                isize::try_from(*self).ok()
+            }
+
+            fn to_bigint(&self) -> BigInt {
+                BigInt::from(*self)
+            }
+
+            fn is_negative(&self) -> bool {
+                *self < 0
+            }
+
+            fn parse_word(token: &str) -> Result<Self, ParseIntError> {
+                token.parse()
+            }
+
+            fn checked_add(&self, other: &Self) -> Option<Self> {
+                $t::checked_add(*self, *other)
+            }
+
+            fn checked_mul(&self, other: &Self) -> Option<Self> {
+                $t::checked_mul(*self, *other)
+            }
+
+            fn wrapping_add(&self, other: &Self) -> Self {
+                $t::wrapping_add(*self, *other)
+            }
+
+            fn wrapping_mul(&self, other: &Self) -> Self {
+                $t::wrapping_mul(*self, *other)
+            }
+
+            fn saturating_add(&self, other: &Self) -> Self {
+                $t::saturating_add(*self, *other)
+            }
+
+            fn saturating_mul(&self, other: &Self) -> Self {
+                $t::saturating_mul(*self, *other)
+            }
+        }
+    )*};
+}
+
+impl_word!(i64, i128);
+
+/// Never overflows, so every `Overflow` policy gives the exact result.
+impl Word for BigInt {
+    fn from_isize(i: isize) -> Self {
+        BigInt::from(i)
+    }
+
+    fn to_isize(&self) -> Option<isize> {
+        ToPrimitive::to_isize(self)
+    }
+
+    fn to_bigint(&self) -> BigInt {
+        self.clone()
+    }
+
+    fn is_negative(&self) -> bool {
+        Signed::is_negative(self)
+    }
+
+    fn parse_word(token: &str) -> Result<Self, ParseIntError> {
+        // `ParseIntError` cannot be constructed directly, so borrow one from
+        // a primitive parse that fails on the same token.
+        token.parse().map_err(|_| match token.parse::<i8>() {
+            Err(err) => err,
+            Ok(_) => unreachable!("BigInt rejected {:?}", token),
+        })
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        Some(self + other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        Some(self * other)
+    }
+
+    fn wrapping_add(&self, other: &Self) -> Self {
+        self + other
+    }
+
+    fn wrapping_mul(&self, other: &Self) -> Self {
+        self * other
+    }
+
+    fn saturating_add(&self, other: &Self) -> Self {
+        self + other
+    }
+
+    fn saturating_mul(&self, other: &Self) -> Self {
+        self * other
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, Deserialize, Eq, PartialEq, PartialOrd, Ord, Serialize)]
+pub struct Value<W = isize>(pub W);
+
+/// What arithmetic does when its result does not fit in a `Value`: return
+/// `None` (which a machine turns into a fault), wrap around, or saturate at
+/// the nearest bound. The behaviour is the same in debug and release builds.
+///
+/// `Promote` returns `None` like `Trap`, but a machine that faults on an
+/// overflowing `add` or `mul` under it can be converted to `BigInt` words
+/// with `Machine::promote`, and then retries the instruction exactly.
+#[derive(Copy, Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub enum Overflow {
+    #[default]
+    Trap,
+    Wrap,
+    Saturate,
+    Promote,
+}
+
+impl<W: Word> Value<W> {
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        self.0.checked_add(&other.0).map(Value)
+    }
+
+    pub fn checked_mul(self, other: Self) -> Option<Self> {
+        self.0.checked_mul(&other.0).map(Value)
+    }
+
+    pub fn add_with(self, other: Self, overflow: Overflow) -> Option<Self> {
+        match overflow {
+            Overflow::Trap | Overflow::Promote => self.checked_add(other),
+            Overflow::Wrap => Some(Value(self.0.wrapping_add(&other.0))),
+            Overflow::Saturate => Some(Value(self.0.saturating_add(&other.0))),
+        }
+    }
+
+    pub fn mul_with(self, other: Self, overflow: Overflow) -> Option<Self> {
+        match overflow {
+            Overflow::Trap | Overflow::Promote => self.checked_mul(other),
+            Overflow::Wrap => Some(Value(self.0.wrapping_mul(&other.0))),
+            Overflow::Saturate => Some(Value(self.0.saturating_mul(&other.0))),
+        }
+    }
+}
+
+/// Panics on overflow, in every build profile.
+impl<W: Word> std::ops::Add for Value<W> {
+    type Output = Value<W>;
     fn add(self, other: Self) -> Self {
-        Value(self.0 + other.0)
+        self.checked_add(other).expect("value overflow")
     }
 }
 
-impl std::ops::Mul for Value {
-    type Output = Value;
+/// Panics on overflow, in every build profile.
+impl<W: Word> std::ops::Mul for Value<W> {
+    type Output = Value<W>;
     fn mul(self, other: Self) -> Self {
-        Value(self.0 * other.0)
+        self.checked_mul(other).expect("value overflow")
     }
 }
 
-impl Into<bool> for Value {
-    fn into(self) -> bool {
-        self.0 != 0
+impl<W: Word> From<Value<W>> for bool {
+    fn from(value: Value<W>) -> Self {
+        !value.0.is_zero()
     }
 }
 
-impl From<isize> for Value {
+impl<W: Word> From<isize> for Value<W> {
     fn from(i: isize) -> Self {
-        Self(i)
+        Self(W::from_isize(i))
     }
 }
 
-impl From<&isize> for Value {
+impl<W: Word> From<&isize> for Value<W> {
     fn from(i: &isize) -> Self {
-        Self(*i)
+        Self(W::from_isize(*i))
     }
 }
 
-impl From<bool> for Value {
+impl<W: Word> From<bool> for Value<W> {
     fn from(b: bool) -> Self {
         match b {
-            true => Self(1),
-            false => Self(0),
+            true => 1.into(),
+            false => 0.into(),
         }
     }
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Deserialize, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize)]
 pub struct Address(pub usize);
 
 pub const NOUN_ADDRESS: Address = Address(1);
@@ -58,13 +295,46 @@ impl From<usize> for Address {
     }
 }
 
-impl From<Value> for Address {
-    fn from(value: Value) -> Self {
-        Address(value.0 as usize)
+impl<W: Word> TryFrom<Value<W>> for Address {
+    type Error = AddressError;
+
+    fn try_from(value: Value<W>) -> Result<Self, Self::Error> {
+        match value.0.to_isize() {
+            Some(address) if address < 0 => Err(AddressError::Negative(address)),
+            Some(address) => Ok(Address(address as usize)),
+            None => Err(AddressError::TooLarge),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum AddressError {
+    Negative(isize),
+    OverLimit {
+        address: Address,
+        limit: usize,
+    },
+    /// An address or offset that does not fit in an `isize`.
+    TooLarge,
+}
+
+impl fmt::Display for AddressError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AddressError::Negative(address) => write!(f, "negative address {}", address),
+            AddressError::OverLimit { address, limit } => write!(
+                f,
+                "address {} is past the memory limit of {} values",
+                address.0, limit
+            ),
+            AddressError::TooLarge => write!(f, "address out of range"),
+        }
     }
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+impl std::error::Error for AddressError {}
+
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub struct Offset(pub isize);
 
 impl From<isize> for Offset {
@@ -73,9 +343,11 @@ impl From<isize> for Offset {
     }
 }
 
-impl From<Value> for Offset {
-    fn from(value: Value) -> Self {
-        Offset(value.0)
+impl<W: Word> TryFrom<Value<W>> for Offset {
+    type Error = AddressError;
+
+    fn try_from(value: Value<W>) -> Result<Self, Self::Error> {
+        value.0.to_isize().map(Offset).ok_or(AddressError::TooLarge)
     }
 }
 
@@ -92,11 +364,42 @@ impl std::ops::AddAssign for Offset {
     }
 }
 
+impl Offset {
+    /// Adds `value` to the offset with `overflow`, in the machine's words.
+    /// A sum that does not fit in an `isize` saturates under `Saturate`, and
+    /// otherwise overflows, returning `None`.
+    pub fn add_with<W: Word>(self, value: Value<W>, overflow: Overflow) -> Option<Offset> {
+        let sum = Value(W::from_isize(self.0)).add_with(value, overflow)?;
+        match sum.0.to_isize() {
+            Some(offset) => Some(Offset(offset)),
+            None if overflow == Overflow::Saturate => Some(Offset(sum.0.saturating_to_isize())),
+            None => None,
+        }
+    }
+
+    /// Resolves a relative-mode parameter against the relative base.
+    pub fn resolve(self, rel_base: Offset) -> Result<Address, AddressError> {
+        let address = self.0.checked_add(rel_base.0);
+        Address::try_from(Value(address.ok_or(AddressError::TooLarge)?))
+    }
+}
+
+impl Address {
+    pub fn checked_add(self, offset: Offset) -> Result<Address, AddressError> {
+        let address = (self.0 as isize).checked_add(offset.0);
+        Address::try_from(Value(address.ok_or(AddressError::TooLarge)?))
+    }
+}
+
+/// Panics if the result is negative; use `Address::checked_add` for offsets
+/// that come from a program.
 impl std::ops::Add<Offset> for Address {
     type Output = Address;
     fn add(self, offset: Offset) -> Self::Output {
-        let i = self.0 as isize;
-        Address((i + offset.0) as usize)
+        match self.checked_add(offset) {
+            Ok(address) => address,
+            Err(err) => panic!("{}", err),
+        }
     }
 }
 
@@ -106,79 +409,555 @@ impl std::ops::AddAssign<Offset> for Address {
     }
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
-pub struct Memory {
-    values: Vec<Value>,
+/// Storage for the values in a `Memory`. Every address at or past `len`
+/// reads as zero, and writing to one extends `len` to cover it, so backends
+/// differ only in how they store values, not in what programs see.
+pub trait MemoryBackend: Sized {
+    type Word: Word;
+
+    fn from_values(values: Vec<Value<Self::Word>>) -> Self;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the value at `address`, or `None` if it is not stored and so
+    /// reads as zero.
+    fn get(&self, address: Address) -> Option<&Value<Self::Word>>;
+
+    fn get_mut(&mut self, address: Address) -> &mut Value<Self::Word>;
+
+    /// Shrinks the backend to `len` values, if it has grown past that.
+    fn truncate(&mut self, len: usize);
+}
+
+/// A single `Vec` covering every address up to the highest one written.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Dense<W = isize> {
+    values: Vec<Value<W>>,
+}
+
+impl<W: Word> MemoryBackend for Dense<W> {
+    type Word = W;
+
+    fn from_values(values: Vec<Value<W>>) -> Self {
+        Dense { values }
+    }
+
+    fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    fn get(&self, address: Address) -> Option<&Value<W>> {
+        self.values.get(address.0)
+    }
+
+    fn get_mut(&mut self, address: Address) -> &mut Value<W> {
+        if address.0 >= self.values.len() {
+            let additional = address.0 - self.values.len() + 1;
+            self.values.reserve(additional);
+            self.values
+                .extend(iter::repeat_n(Value::default(), additional));
+        }
+        &mut self.values[address.0]
+    }
+
+    fn truncate(&mut self, len: usize) {
+        self.values.truncate(len);
+    }
+}
+
+const PAGE_SIZE: usize = 1024;
+
+/// Fixed-size pages allocated only when written to, so that programs can
+/// use a few far-apart addresses without allocating everything between.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(try_from = "PagedParts<W>")]
+pub struct Paged<W = isize> {
+    pages: HashMap<usize, Box<[Value<W>]>>,
+    len: usize,
+}
+
+/// The fields of a `Paged`, deserialized before its pages are checked.
+#[derive(Deserialize)]
+struct PagedParts<W> {
+    pages: HashMap<usize, Box<[Value<W>]>>,
+    len: usize,
+}
+
+impl<W> TryFrom<PagedParts<W>> for Paged<W> {
+    type Error = String;
+
+    fn try_from(parts: PagedParts<W>) -> Result<Self, Self::Error> {
+        for (index, page) in &parts.pages {
+            if page.len() != PAGE_SIZE {
+                return Err(format!(
+                    "page {} has {} values, not {}",
+                    index,
+                    page.len(),
+                    PAGE_SIZE
+                ));
+            }
+            if *index >= parts.len.div_ceil(PAGE_SIZE) {
+                return Err(format!("page {} is past the length {}", index, parts.len));
+            }
+        }
+        Ok(Paged {
+            pages: parts.pages,
+            len: parts.len,
+        })
+    }
+}
+
+impl<W: Word> Paged<W> {
+    /// Returns whether page `index` holds the same values in both memories.
+    fn page_eq(&self, other: &Self, index: usize) -> bool {
+        let is_zero = |page: &[Value<W>]| page.iter().all(|value| value.0.is_zero());
+        match (self.pages.get(&index), other.pages.get(&index)) {
+            (Some(page), Some(other)) => page == other,
+            (Some(page), None) | (None, Some(page)) => is_zero(page),
+            (None, None) => true,
+        }
+    }
+}
+
+impl<W: Word> MemoryBackend for Paged<W> {
+    type Word = W;
+
+    fn from_values(values: Vec<Value<W>>) -> Self {
+        let pages = values
+            .chunks(PAGE_SIZE)
+            .enumerate()
+            .filter(|(_, chunk)| chunk.iter().any(|value| !value.0.is_zero()))
+            .map(|(index, chunk)| {
+                let mut page = vec![Value::default(); PAGE_SIZE];
+                page[..chunk.len()].clone_from_slice(chunk);
+                (index, page.into_boxed_slice())
+            })
+            .collect();
+        Paged {
+            pages,
+            len: values.len(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn get(&self, address: Address) -> Option<&Value<W>> {
+        let page = self.pages.get(&(address.0 / PAGE_SIZE))?;
+        Some(&page[address.0 % PAGE_SIZE])
+    }
+
+    fn get_mut(&mut self, address: Address) -> &mut Value<W> {
+        self.len = self.len.max(address.0 + 1);
+        let page = self
+            .pages
+            .entry(address.0 / PAGE_SIZE)
+            .or_insert_with(|| vec![Value::default(); PAGE_SIZE].into_boxed_slice());
+        &mut page[address.0 % PAGE_SIZE]
+    }
+
+    fn truncate(&mut self, len: usize) {
+        if len >= self.len {
+            return;
+        }
+        self.pages.retain(|&index, _| index * PAGE_SIZE < len);
+        if let Some(page) = self.pages.get_mut(&(len / PAGE_SIZE)) {
+            page[len % PAGE_SIZE..]
+                .iter_mut()
+                .for_each(|value| *value = Value::default());
+        }
+        self.len = len;
+    }
+}
+
+/// Pages that are not allocated compare equal to pages of zeros.
+impl<W: Word> PartialEq for Paged<W> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len
+            && self
+                .pages
+                .keys()
+                .chain(other.pages.keys())
+                .all(|&index| self.page_eq(other, index))
+    }
+}
+
+impl<W: Word> Eq for Paged<W> {}
+
+/// A `Vec` shared between clones until one of them writes to it, which makes
+/// cloning a machine cheap when most clones only read their memory.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct CopyOnWrite<W = isize> {
+    values: Arc<Vec<Value<W>>>,
+}
+
+impl<W> CopyOnWrite<W> {
+    /// Returns whether this memory still shares its values with a clone.
+    pub fn is_shared(&self) -> bool {
+        Arc::strong_count(&self.values) > 1
+    }
+}
+
+impl<W: Word> MemoryBackend for CopyOnWrite<W> {
+    type Word = W;
+
+    fn from_values(values: Vec<Value<W>>) -> Self {
+        CopyOnWrite {
+            values: Arc::new(values),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    fn get(&self, address: Address) -> Option<&Value<W>> {
+        self.values.get(address.0)
+    }
+
+    fn get_mut(&mut self, address: Address) -> &mut Value<W> {
+        let values = Arc::make_mut(&mut self.values);
+        if address.0 >= values.len() {
+            let additional = address.0 - values.len() + 1;
+            values.reserve(additional);
+            values.extend(iter::repeat_n(Value::default(), additional));
+        }
+        &mut values[address.0]
+    }
+
+    fn truncate(&mut self, len: usize) {
+        if len < self.values.len() {
+            Arc::make_mut(&mut self.values).truncate(len);
+        }
+    }
+}
+
+/// Entries per page of a `PageTable`. Every machine fills its own tables,
+/// so their pages are much smaller than those of `Paged`, and machines that
+/// only run a little code do not pay to allocate a lot of it.
+const TABLE_PAGE_SIZE: usize = 64;
+
+/// Pages per directory of a `PageTable`.
+const TABLE_DIRECTORY_SIZE: usize = 256;
+
+type TablePage<T> = Box<[Option<T>; TABLE_PAGE_SIZE]>;
+type TableDirectory<T> = Box<[Option<TablePage<T>>; TABLE_DIRECTORY_SIZE]>;
+
+/// Allocates an array of `N` entries without building it on the stack.
+fn boxed_array<T, const N: usize>(new: impl FnMut() -> T) -> Box<[T; N]> {
+    let entries = iter::repeat_with(new).take(N).collect::<Box<[_]>>();
+    match entries.try_into() {
+        Ok(entries) => entries,
+        Err(_) => unreachable!("collected {} entries", N),
+    }
+}
+
+/// Entries kept by address, for what is cached about the code in memory.
+/// Like `Paged`, a page of entries is only allocated once something is
+/// stored in it, but pages are found by indexing, through a directory of
+/// pages, rather than by hashing.
+#[derive(Clone)]
+pub(crate) struct PageTable<T> {
+    directories: Vec<Option<TableDirectory<T>>>,
+    /// No entry reaches this address, so writes at or past it can skip
+    /// looking for entries to invalidate.
+    end: usize,
+}
+
+impl<T> PageTable<T> {
+    const DIRECTORY_SPAN: usize = TABLE_PAGE_SIZE * TABLE_DIRECTORY_SIZE;
+
+    fn entry(&self, address: Address) -> Option<&Option<T>> {
+        let directory = self.directories.get(address.0 / Self::DIRECTORY_SPAN)?;
+        let page =
+            directory.as_ref()?[address.0 / TABLE_PAGE_SIZE % TABLE_DIRECTORY_SIZE].as_ref()?;
+        Some(&page[address.0 % TABLE_PAGE_SIZE])
+    }
+
+    fn entry_mut(&mut self, address: Address) -> Option<&mut Option<T>> {
+        let directory = self.directories.get_mut(address.0 / Self::DIRECTORY_SPAN)?;
+        let page =
+            directory.as_mut()?[address.0 / TABLE_PAGE_SIZE % TABLE_DIRECTORY_SIZE].as_mut()?;
+        Some(&mut page[address.0 % TABLE_PAGE_SIZE])
+    }
+
+    pub(crate) fn get(&self, address: Address) -> Option<&T> {
+        self.entry(address)?.as_ref()
+    }
+
+    /// Stores `entry` at `address`, as covering `len` values from there.
+    pub(crate) fn insert(&mut self, address: Address, len: usize, entry: T) {
+        let index = address.0 / Self::DIRECTORY_SPAN;
+        if index >= self.directories.len() {
+            self.directories.resize_with(index + 1, || None);
+        }
+        let directory = self.directories[index].get_or_insert_with(|| boxed_array(|| None));
+        let page = &mut directory[address.0 / TABLE_PAGE_SIZE % TABLE_DIRECTORY_SIZE];
+        let page = page.get_or_insert_with(|| boxed_array(|| None));
+        page[address.0 % TABLE_PAGE_SIZE] = Some(entry);
+        self.end = self.end.max(address.0.saturating_add(len));
+    }
+
+    /// Calls `f` with the address and entry of everything that could cover
+    /// `address`: the entries up to `MAX_INSTRUCTION_LEN - 1` values before
+    /// it, and the one at it.
+    pub(crate) fn overlapping(
+        &mut self,
+        address: Address,
+        mut f: impl FnMut(Address, &mut Option<T>),
+    ) {
+        if address.0 >= self.end {
+            return;
+        }
+        let first = address.0.saturating_sub(op::MAX_INSTRUCTION_LEN - 1);
+        for start in first..=address.0 {
+            if let Some(entry) = self.entry_mut(Address(start)) {
+                f(Address(start), entry);
+            }
+        }
+    }
+
+    /// Drops every entry that could cover `len` or anything past it: those
+    /// starting there or later, and up to `MAX_INSTRUCTION_LEN - 1` values
+    /// before it.
+    pub(crate) fn truncate(&mut self, len: usize) {
+        if len >= self.end {
+            return;
+        }
+        let first = len.saturating_sub(op::MAX_INSTRUCTION_LEN - 1);
+        for start in first..first.next_multiple_of(TABLE_PAGE_SIZE) {
+            if let Some(entry) = self.entry_mut(Address(start)) {
+                *entry = None;
+            }
+        }
+        let first_page = first.div_ceil(TABLE_PAGE_SIZE);
+        let directories = first_page.div_ceil(TABLE_DIRECTORY_SIZE);
+        self.directories.truncate(directories);
+        if let Some(Some(directory)) = self.directories.get_mut(first_page / TABLE_DIRECTORY_SIZE) {
+            let pages = &mut directory[first_page % TABLE_DIRECTORY_SIZE..];
+            pages.iter_mut().for_each(|page| *page = None);
+        }
+        self.end = len;
+    }
+}
+
+impl<T> Default for PageTable<T> {
+    fn default() -> Self {
+        PageTable {
+            directories: Vec::new(),
+            end: 0,
+        }
+    }
+}
+
+/// Instructions already decoded from memory, by address. Every write to
+/// memory invalidates the instructions it overlaps, so programs that modify
+/// their own code still see the new instructions.
+#[derive(Clone, Default)]
+struct DecodeCache<W> {
+    entries: PageTable<op::Instruction<W>>,
+}
+
+impl<W: Word> DecodeCache<W> {
+    fn get(&self, address: Address) -> Option<&op::Instruction<W>> {
+        self.entries.get(address)
+    }
+
+    fn insert(&mut self, address: Address, instruction: op::Instruction<W>) {
+        let len = instruction.opcode().len().0 as usize;
+        self.entries.insert(address, len, instruction);
+    }
+
+    fn invalidate(&mut self, address: Address) {
+        self.entries.overlapping(address, |start, entry| {
+            if let Some(instruction) = entry {
+                if start.0 + instruction.opcode().len().0 as usize > address.0 {
+                    *entry = None;
+                }
+            }
+        });
+    }
+
+    fn truncate(&mut self, len: usize) {
+        self.entries.truncate(len);
+    }
+}
+
+impl<W> fmt::Debug for DecodeCache<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DecodeCache").finish_non_exhaustive()
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Memory<B: MemoryBackend = Dense> {
+    backend: B,
+    /// What addresses that are not stored read as, which `Index` needs a
+    /// reference to.
+    #[serde(skip)]
+    zero: Value<B::Word>,
+    #[serde(skip)]
+    cache: DecodeCache<B::Word>,
+    #[serde(skip)]
+    pub(crate) code: fast::Code<B::Word>,
     pub rel_base: Offset,
+    /// Loads and stores at or past this address fail with
+    /// `AddressError::OverLimit`. Indexing memory directly is not limited.
+    pub limit: usize,
+}
+
+/// Memories are equal if programs would see the same values in them,
+/// whatever either one has cached.
+impl<B: MemoryBackend + PartialEq> PartialEq for Memory<B> {
+    fn eq(&self, other: &Self) -> bool {
+        self.backend == other.backend
+            && self.rel_base == other.rel_base
+            && self.limit == other.limit
+    }
 }
 
-impl Memory {
-    pub fn read_instruction(&self, start: Address) -> op::Instruction {
-        let mem_slice = &self.values[start.0..];
-        mem_slice.into()
+impl<B: MemoryBackend + Eq> Eq for Memory<B> {}
+
+impl<B: MemoryBackend> Memory<B> {
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    pub fn len(&self) -> usize {
+        self.backend.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.backend.is_empty()
+    }
+
+    /// Shrinks memory to `len` values, if it has grown past that.
+    pub fn truncate(&mut self, len: usize) {
+        if len >= self.len() {
+            return;
+        }
+        self.backend.truncate(len);
+        self.cache.truncate(len);
+        self.code.truncate(len);
+    }
+
+    /// Copies memory into backend `C`, with every value as a `BigInt`.
+    pub fn promote<C: MemoryBackend<Word = BigInt>>(&self) -> Memory<C> {
+        let values = (0..self.len()).map(|address| self[Address(address)].0.to_bigint());
+        Memory {
+            rel_base: self.rel_base,
+            limit: self.limit,
+            ..Memory::from(values.map(Value).collect::<Vec<_>>())
+        }
+    }
+
+    pub fn read_instruction(
+        &self,
+        start: Address,
+    ) -> Result<op::Instruction<B::Word>, op::DecodeError<B::Word>> {
+        op::Instruction::try_decode(self, start)
+    }
+
+    /// Reads the instruction at `start` like `read_instruction`, but keeps
+    /// it to return again until memory under it is written to.
+    pub fn read_cached_instruction(
+        &mut self,
+        start: Address,
+    ) -> Result<op::Instruction<B::Word>, op::DecodeError<B::Word>> {
+        if let Some(instruction) = self.cache.get(start) {
+            return Ok(instruction.clone());
+        }
+        let instruction = self.read_instruction(start)?;
+        self.cache.insert(start, instruction.clone());
+        Ok(instruction)
     }
 
-    pub fn store(&mut self, value: Value, store: op::Store) {
-        let base = self.rel_base;
-        match store {
-            op::Store::Position(address) => self[address] = value,
-            op::Store::Relative(address) => self[address + base] = value,
+    fn check_limit(&self, address: Address) -> Result<Address, AddressError> {
+        if address.0 < self.limit {
+            Ok(address)
+        } else {
+            Err(AddressError::OverLimit {
+                address,
+                limit: self.limit,
+            })
         }
     }
 
-    pub fn load(&self, load: op::Load) -> Value {
+    /// Returns the address that `store` writes to.
+    pub fn store_address(&self, store: op::Store) -> Result<Address, AddressError> {
+        let address = match store {
+            op::Store::Position(address) => address,
+            op::Store::Relative(offset) => offset.resolve(self.rel_base)?,
+        };
+        self.check_limit(address)
+    }
+
+    pub fn store(&mut self, value: Value<B::Word>, store: op::Store) -> Result<(), AddressError> {
+        let address = self.store_address(store)?;
+        self[address] = value;
+        Ok(())
+    }
+
+    pub fn load(&self, load: op::Load<B::Word>) -> Result<Value<B::Word>, AddressError> {
         match load {
-            op::Load::Position(address) => self[address],
-            op::Load::Immediate(value) => value,
-            op::Load::Relative(address) => self[address + self.rel_base],
+            op::Load::Position(address) => Ok(self[self.check_limit(address)?].clone()),
+            op::Load::Immediate(value) => Ok(value),
+            op::Load::Relative(offset) => {
+                let address = offset.resolve(self.rel_base)?;
+                Ok(self[self.check_limit(address)?].clone())
+            }
         }
     }
 }
 
-impl From<&Program> for Memory {
-    fn from(program: &Program) -> Self {
-        let values = program.0.clone();
+impl<B: MemoryBackend> From<&Program<B::Word>> for Memory<B> {
+    fn from(program: &Program<B::Word>) -> Self {
         Memory {
-            values: values,
+            backend: B::from_values(program.0.clone()),
+            zero: Value::default(),
+            cache: DecodeCache::default(),
+            code: fast::Code::default(),
             rel_base: 0.into(),
+            limit: usize::MAX,
         }
     }
 }
 
-impl<S, T> From<S> for Memory
+impl<S, T, B> From<S> for Memory<B>
 where
     S: IntoIterator<Item = T>,
-    T: Into<Value>,
+    T: Into<Value<B::Word>>,
+    B: MemoryBackend,
 {
     fn from(slice: S) -> Self {
         let values = slice.into_iter().map(Into::into).collect();
         Memory {
-            values: values,
+            backend: B::from_values(values),
+            zero: Value::default(),
+            cache: DecodeCache::default(),
+            code: fast::Code::default(),
             rel_base: 0.into(),
+            limit: usize::MAX,
         }
     }
 }
 
-impl std::ops::Index<Address> for Memory {
-    type Output = Value;
+impl<B: MemoryBackend> std::ops::Index<Address> for Memory<B> {
+    type Output = Value<B::Word>;
     fn index(&self, addr: Address) -> &Self::Output {
-        if addr.0 < self.values.len() {
-            &self.values[addr.0]
-        } else {
-            &Value(0)
-        }
+        self.backend.get(addr).unwrap_or(&self.zero)
     }
 }
 
-impl std::ops::IndexMut<Address> for Memory {
+impl<B: MemoryBackend> std::ops::IndexMut<Address> for Memory<B> {
     fn index_mut(&mut self, addr: Address) -> &mut Self::Output {
-        if addr.0 < self.values.len() {
-            &mut self.values[addr.0]
-        } else {
-            let additional = addr.0 - self.values.len() + 1;
-            self.values.reserve(additional);
-            self.values.extend(iter::repeat(Value(0)).take(additional));
-            &mut self.values[addr.0]
-        }
+        self.cache.invalidate(addr);
+        self.code.invalidate(addr);
+        self.backend.get_mut(addr)
     }
 }
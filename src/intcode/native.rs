@@ -0,0 +1,218 @@
+//! Support for the Rust code that `intcode::transpile` generates.
+//!
+//! Each helper either does what the instruction it is part of would, or
+//! returns `None` without changing anything if the reference interpreter
+//! would fault, block or need to see the write. Generated code then hands
+//! the machine back to `Machine::run` at the instruction it was on.
+
+use super::mem::{Address, MemoryBackend, Offset, Value};
+use super::trace::Tracer;
+use super::vm::{Input, Machine, Output, Status};
+
+use std::convert::TryFrom;
+
+/// Prepares `machine` to run generated code, returning `false` if it has
+/// stopped, has a tracer to feed, or if any of the `code` ranges of its
+/// memory no longer hold what they did in `program`.
+pub fn enter<I, O, T, B>(
+    machine: &mut Machine<I, O, T, B>,
+    program: &[isize],
+    code: &[(usize, usize)],
+) -> bool
+where
+    I: Input,
+    O: Output,
+    T: Tracer,
+    B: MemoryBackend<Word = isize>,
+{
+    if let Status::Halted | Status::Faulted(_) = machine.status {
+        return false;
+    }
+    if T::ENABLED {
+        return false;
+    }
+    let intact = code.iter().all(|&(start, end)| {
+        (start..end).all(|address| machine.memory[Address(address)].0 == program[address])
+    });
+    if intact {
+        machine.status = Status::Ready;
+    }
+    intact
+}
+
+fn is_code(code: &[(usize, usize)], address: usize) -> bool {
+    let index = code.partition_point(|&(start, _)| start <= address);
+    index > 0 && address < code[index - 1].1
+}
+
+pub fn load<I, O, T, B>(machine: &Machine<I, O, T, B>, address: usize) -> Option<isize>
+where
+    I: Input,
+    O: Output,
+    T: Tracer,
+    B: MemoryBackend<Word = isize>,
+{
+    if address < machine.memory.limit {
+        Some(machine.memory[Address(address)].0)
+    } else {
+        None
+    }
+}
+
+pub fn load_relative<I, O, T, B>(machine: &Machine<I, O, T, B>, offset: isize) -> Option<isize>
+where
+    I: Input,
+    O: Output,
+    T: Tracer,
+    B: MemoryBackend<Word = isize>,
+{
+    let address = Offset(offset).resolve(machine.memory.rel_base).ok()?;
+    load(machine, address.0)
+}
+
+/// Returns `address` if an instruction can store to it without writing
+/// over any of the `code` ranges.
+pub fn store<I, O, T, B>(
+    machine: &Machine<I, O, T, B>,
+    code: &[(usize, usize)],
+    address: usize,
+) -> Option<usize>
+where
+    I: Input,
+    O: Output,
+    T: Tracer,
+    B: MemoryBackend<Word = isize>,
+{
+    if address < machine.memory.limit && !is_code(code, address) {
+        Some(address)
+    } else {
+        None
+    }
+}
+
+pub fn store_relative<I, O, T, B>(
+    machine: &Machine<I, O, T, B>,
+    code: &[(usize, usize)],
+    offset: isize,
+) -> Option<usize>
+where
+    I: Input,
+    O: Output,
+    T: Tracer,
+    B: MemoryBackend<Word = isize>,
+{
+    let address = Offset(offset).resolve(machine.memory.rel_base).ok()?;
+    store(machine, code, address.0)
+}
+
+pub fn set<I, O, T, B>(machine: &mut Machine<I, O, T, B>, address: usize, value: isize)
+where
+    I: Input,
+    O: Output,
+    T: Tracer,
+    B: MemoryBackend<Word = isize>,
+{
+    machine.memory[Address(address)] = Value(value);
+}
+
+pub fn add<I, O, T, B>(machine: &Machine<I, O, T, B>, x: isize, y: isize) -> Option<isize>
+where
+    I: Input,
+    O: Output,
+    T: Tracer,
+    B: MemoryBackend<Word = isize>,
+{
+    Value(x)
+        .add_with(Value(y), machine.overflow)
+        .map(|value| value.0)
+}
+
+pub fn mul<I, O, T, B>(machine: &Machine<I, O, T, B>, x: isize, y: isize) -> Option<isize>
+where
+    I: Input,
+    O: Output,
+    T: Tracer,
+    B: MemoryBackend<Word = isize>,
+{
+    Value(x)
+        .mul_with(Value(y), machine.overflow)
+        .map(|value| value.0)
+}
+
+pub fn less_than(x: isize, y: isize) -> isize {
+    (x < y).into()
+}
+
+pub fn equals(x: isize, y: isize) -> isize {
+    (x == y).into()
+}
+
+pub fn input<I, O, T, B>(machine: &mut Machine<I, O, T, B>) -> Option<isize>
+where
+    I: Input,
+    O: Output,
+    T: Tracer,
+    B: MemoryBackend<Word = isize>,
+{
+    machine.input.read_input().map(|value| value.0)
+}
+
+pub fn output<I, O, T, B>(machine: &mut Machine<I, O, T, B>, value: isize)
+where
+    I: Input,
+    O: Output,
+    T: Tracer,
+    B: MemoryBackend<Word = isize>,
+{
+    machine.output.write_output(Value(value));
+}
+
+pub fn set_rel_base<I, O, T, B>(machine: &mut Machine<I, O, T, B>, value: isize) -> Option<()>
+where
+    I: Input,
+    O: Output,
+    T: Tracer,
+    B: MemoryBackend<Word = isize>,
+{
+    let rel_base = machine.memory.rel_base;
+    machine.memory.rel_base = rel_base.add_with(Value(value), machine.overflow)?;
+    Some(())
+}
+
+/// Returns `target` as an address if a jump to it would not fault.
+pub fn jump<I, O, T, B>(machine: &Machine<I, O, T, B>, target: isize) -> Option<usize>
+where
+    I: Input,
+    O: Output,
+    T: Tracer,
+    B: MemoryBackend<Word = isize>,
+{
+    match Address::try_from(Value(target)) {
+        Ok(address) if address.0 < machine.memory.len() => Some(address.0),
+        _ => None,
+    }
+}
+
+/// Finishes an instruction, moving on to the one at `next`.
+pub fn retire<I, O, T, B>(machine: &mut Machine<I, O, T, B>, next: usize)
+where
+    I: Input,
+    O: Output,
+    T: Tracer,
+    B: MemoryBackend<Word = isize>,
+{
+    machine.ins_ptr = Address(next);
+    machine.retired += 1;
+}
+
+pub fn halt<I, O, T, B>(machine: &mut Machine<I, O, T, B>) -> Status
+where
+    I: Input,
+    O: Output,
+    T: Tracer,
+    B: MemoryBackend<Word = isize>,
+{
+    machine.retired += 1;
+    machine.status = Status::Halted;
+    Status::Halted
+}
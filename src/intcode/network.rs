@@ -0,0 +1,367 @@
+//! Running several machines that feed each other's inputs.
+//!
+//! A `Network` runs its machines in rounds, giving each one that has not
+//! stopped a turn until it blocks or stops, and sending whatever it output on
+//! to other machines' inputs as its `Wiring` says. A round in which no
+//! machine reads or sends anything leaves the network quiet: every machine
+//! has halted or faulted, or is waiting for input that no machine has sent.
+//! The caller can then inject values to wake it up, or stop it.
+//!
+//! Machines that poll for input rather than block on it, such as network
+//! interfaces that read `-1` when no packet has arrived, can be given an
+//! `idle_input` to read instead; reading it does not count as activity.
+
+use super::mem::{Dense, MemoryBackend, Value};
+use super::trace::{NoTracer, Tracer};
+use super::vm::{DefaultInput, DefaultOutput, Machine, Status};
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// Returns the indices of the machines that a value output by the machine
+/// at the given index goes to.
+pub type Route<W> = Box<dyn FnMut(usize, &Value<W>) -> Vec<usize>>;
+
+/// A machine whose inputs and outputs a `Network` can pass around.
+pub type NetworkMachine<T = NoTracer, B = Dense> = Machine<
+    DefaultInput<<B as MemoryBackend>::Word>,
+    DefaultOutput<<B as MemoryBackend>::Word>,
+    T,
+    B,
+>;
+
+/// Where each machine's outputs go.
+pub enum Wiring<W = isize> {
+    /// Each machine feeds the next; the last machine's outputs go nowhere.
+    Pipeline,
+    /// Each machine feeds the next, and the last feeds the first.
+    Ring,
+    /// The machine at the given index feeds every other machine, and they
+    /// all feed it.
+    Star(usize),
+    /// Sends each value wherever the function says.
+    Custom(Route<W>),
+}
+
+impl<W> fmt::Debug for Wiring<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Wiring::Pipeline => write!(f, "Pipeline"),
+            Wiring::Ring => write!(f, "Ring"),
+            Wiring::Star(hub) => f.debug_tuple("Star").field(hub).finish(),
+            Wiring::Custom(_) => write!(f, "Custom(..)"),
+        }
+    }
+}
+
+impl<W> Wiring<W> {
+    pub(crate) fn destinations(&mut self, from: usize, value: &Value<W>, len: usize) -> Vec<usize> {
+        match self {
+            Wiring::Pipeline if from + 1 < len => vec![from + 1],
+            Wiring::Pipeline => vec![],
+            Wiring::Ring => vec![(from + 1) % len],
+            Wiring::Star(hub) if from == *hub => (0..len).filter(|&to| to != from).collect(),
+            Wiring::Star(hub) => vec![*hub],
+            Wiring::Custom(route) => route(from, value),
+        }
+    }
+
+    /// Returns the machines that are wired to send to the one at `to`, which
+    /// are not known for `Wiring::Custom`.
+    fn senders(&self, to: usize, len: usize) -> Vec<usize> {
+        match *self {
+            Wiring::Pipeline if to > 0 => vec![to - 1],
+            Wiring::Pipeline | Wiring::Custom(_) => vec![],
+            Wiring::Ring => vec![(to + len - 1) % len],
+            Wiring::Star(hub) if to == hub => (0..len).filter(|&from| from != to).collect(),
+            Wiring::Star(hub) => vec![hub],
+        }
+    }
+}
+
+/// A machine blocked on input, and the machines it could come from: those
+/// its wiring connects to it, and any others that have sent to it before.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Wait {
+    pub machine: usize,
+    pub senders: Vec<usize>,
+}
+
+/// The machines of a quiet network that are still waiting for input.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Idle {
+    pub waiting: Vec<Wait>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Outcome {
+    /// Every machine has halted or faulted.
+    Stopped,
+    /// The network went quiet, and nothing was injected to wake it up.
+    Deadlock(Idle),
+    /// The network went quiet, and the caller stopped it.
+    Interrupted(Idle),
+}
+
+#[derive(Debug)]
+pub struct Network<T = NoTracer, B = Dense>
+where
+    T: Tracer<B::Word>,
+    B: MemoryBackend,
+{
+    pub machines: Vec<NetworkMachine<T, B>>,
+    pub wiring: Wiring<B::Word>,
+    /// What a machine reads when nothing has been sent to it, if it should
+    /// not block.
+    pub idle_input: Option<Value<B::Word>>,
+    /// Everything each machine has output, wherever it was sent.
+    pub outputs: Vec<Vec<Value<B::Word>>>,
+    pub(crate) senders: Vec<BTreeSet<usize>>,
+}
+
+impl<T, B> Network<T, B>
+where
+    T: Tracer<B::Word>,
+    B: MemoryBackend,
+{
+    pub fn new(machines: Vec<NetworkMachine<T, B>>, wiring: Wiring<B::Word>) -> Self {
+        let outputs = machines.iter().map(|_| vec![]).collect();
+        let senders = machines.iter().map(|_| BTreeSet::new()).collect();
+        Network {
+            machines,
+            wiring,
+            idle_input: None,
+            outputs,
+            senders,
+        }
+    }
+
+    /// Runs the machines until the network goes quiet.
+    ///
+    /// Panics if a `Wiring::Custom` function returns an index out of range.
+    pub fn run(&mut self) -> Outcome {
+        self.run_with(|_, _| true)
+    }
+
+    /// Runs the machines like `run`, but calls `on_idle` whenever the network
+    /// goes quiet with machines still waiting. It can inject values before
+    /// returning `true` to resume, or return `false` to stop. Resuming
+    /// without injecting anything for a waiting machine is a deadlock.
+    pub fn run_with<F>(&mut self, mut on_idle: F) -> Outcome
+    where
+        F: FnMut(&mut Self, &Idle) -> bool,
+    {
+        loop {
+            if self.round() {
+                continue;
+            }
+            let idle = self.idle();
+            if idle.waiting.is_empty() {
+                return Outcome::Stopped;
+            }
+            if !on_idle(self, &idle) {
+                return Outcome::Interrupted(idle);
+            }
+            let woken = idle
+                .waiting
+                .iter()
+                .any(|wait| !self.machines[wait.machine].input.queue.is_empty());
+            if !woken {
+                return Outcome::Deadlock(idle);
+            }
+        }
+    }
+
+    /// Sends `value` to the machine at index `to`.
+    pub fn inject(&mut self, to: usize, value: Value<B::Word>) {
+        self.machines[to].input.queue.push_back(value);
+    }
+
+    /// Returns the machines that are blocked on input, and where they are
+    /// waiting for it from.
+    pub fn idle(&self) -> Idle {
+        let len = self.machines.len();
+        let waiting = self.machines.iter().enumerate();
+        let waiting = waiting.filter(|(_, machine)| machine.status == Status::Blocked);
+        let waiting = waiting.map(|(to, _)| {
+            let mut senders = self.wiring.senders(to, len);
+            senders.extend(&self.senders[to]);
+            senders.sort_unstable();
+            senders.dedup();
+            Wait {
+                machine: to,
+                senders,
+            }
+        });
+        Idle {
+            waiting: waiting.collect(),
+        }
+    }
+
+    /// Gives every machine that has not stopped a turn, and returns whether
+    /// any of them read or sent anything.
+    fn round(&mut self) -> bool {
+        let mut active = false;
+        for from in 0..self.machines.len() {
+            let machine = &mut self.machines[from];
+            if let Status::Halted | Status::Faulted(_) = machine.status {
+                continue;
+            }
+            match &self.idle_input {
+                _ if !machine.input.queue.is_empty() => active = true,
+                Some(value) => machine.input.queue.push_back(value.clone()),
+                None => (),
+            }
+            machine.run();
+            let values = machine.output.buffer.drain(..).collect::<Vec<_>>();
+            active |= !values.is_empty();
+            for value in values {
+                self.send(from, value);
+            }
+        }
+        active
+    }
+
+    fn send(&mut self, from: usize, value: Value<B::Word>) {
+        let destinations = self.wiring.destinations(from, &value, self.machines.len());
+        for to in destinations {
+            self.senders[to].insert(from);
+            self.machines[to].input.queue.push_back(value.clone());
+        }
+        self.outputs[from].push(value);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::intcode::*;
+
+    /// Doubles every value it reads, forever.
+    const DOUBLER: &[isize] = &[3, 11, 1002, 11, 2, 11, 4, 11, 1105, 1, 0, 0];
+
+    fn values(values: &[isize]) -> Vec<mem::Value> {
+        values.iter().map(Into::into).collect()
+    }
+
+    fn connect(programs: &[&[isize]], wiring: Wiring) -> Network {
+        let machines = programs
+            .iter()
+            .map(|&program| Machine::default_io(&Program::from(program)))
+            .collect();
+        Network::new(machines, wiring)
+    }
+
+    #[test]
+    fn test_pipeline_stops_when_all_are_blocked() {
+        let mut network = connect(&[DOUBLER, DOUBLER, DOUBLER], Wiring::Pipeline);
+        network.machines[0].input.queue.push_back(1.into());
+        network.machines[0].input.queue.push_back(5.into());
+        assert!(matches!(network.run(), Outcome::Deadlock(_)));
+        assert_eq!(network.outputs[0], values(&[2, 10]));
+        assert_eq!(network.outputs[2], values(&[8, 40]));
+    }
+
+    #[test]
+    fn test_star() {
+        let hub: &[isize] = &[104, 1, 104, 2, 99];
+        let mut network = connect(&[DOUBLER, hub, DOUBLER], Wiring::Star(1));
+        assert!(matches!(network.run(), Outcome::Deadlock(_)));
+        assert_eq!(network.machines[1].status, vm::Status::Halted);
+        assert_eq!(network.outputs[0], values(&[2, 4]));
+        assert_eq!(network.outputs[2], values(&[2, 4]));
+        assert_eq!(network.machines[1].input.queue, values(&[2, 4, 2, 4]));
+    }
+
+    #[test]
+    fn test_custom_routing() {
+        let source: &[isize] = &[104, 1, 104, 2, 104, 3, 104, 4, 99];
+        let wiring = Wiring::Custom(Box::new(|from, value: &mem::Value| match from {
+            0 if value.0 % 2 == 1 => vec![1],
+            0 => vec![2],
+            _ => vec![],
+        }));
+        let mut network = connect(&[source, DOUBLER, DOUBLER], wiring);
+        assert!(matches!(network.run(), Outcome::Deadlock(_)));
+        assert_eq!(network.outputs[1], values(&[2, 6]));
+        assert_eq!(network.outputs[2], values(&[4, 8]));
+    }
+
+    #[test]
+    fn test_deadlock_reports_waits() {
+        let mut network = connect(&[DOUBLER, DOUBLER, DOUBLER], Wiring::Ring);
+        let waiting = vec![
+            Wait {
+                machine: 0,
+                senders: vec![2],
+            },
+            Wait {
+                machine: 1,
+                senders: vec![0],
+            },
+            Wait {
+                machine: 2,
+                senders: vec![1],
+            },
+        ];
+        assert_eq!(network.run(), Outcome::Deadlock(Idle { waiting }));
+
+        let mut network = connect(&[&[104, 1, 99], &[3, 0, 99]], Wiring::Pipeline);
+        assert_eq!(network.run(), Outcome::Stopped);
+    }
+
+    #[test]
+    fn test_inject_on_idle() {
+        let mut network = connect(&[DOUBLER, DOUBLER], Wiring::Pipeline);
+        let mut inputs = vec![3, 2, 1];
+        let outcome = network.run_with(|network, idle| {
+            assert_eq!(idle.waiting.len(), 2);
+            match inputs.pop() {
+                Some(input) => network.inject(0, input.into()),
+                None => return false,
+            }
+            true
+        });
+        assert!(matches!(outcome, Outcome::Interrupted(_)));
+        assert_eq!(network.outputs[1], values(&[4, 8, 12]));
+    }
+
+    #[test]
+    fn test_polling_machines() {
+        let poller = asm::assemble(
+            "
+            loop:   in x
+                    eq x, #-1, t
+                    jt t, #loop
+                    out x
+                    jt #1, #loop
+            x:      .data 0
+            t:      .data 0
+            ",
+        )
+        .unwrap();
+        let machine = Machine::default_io(&poller);
+        let mut network = Network::new(vec![machine; 2], Wiring::Pipeline);
+        network.idle_input = Some((-1).into());
+        network.inject(0, 7.into());
+        let mut idles = 0;
+        let outcome = network.run_with(|network, _| {
+            idles += 1;
+            network.inject(0, 8.into());
+            idles < 2
+        });
+        assert_eq!(idles, 2);
+        assert_eq!(network.outputs[1], values(&[7, 8]));
+        let waiting = vec![
+            Wait {
+                machine: 0,
+                senders: vec![],
+            },
+            Wait {
+                machine: 1,
+                senders: vec![0],
+            },
+        ];
+        assert_eq!(outcome, Outcome::Interrupted(Idle { waiting }));
+    }
+}
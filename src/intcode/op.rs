@@ -1,20 +1,27 @@
-use super::mem;
+// num-derive 0.3 puts the impls it derives inside a `const _` block.
+#![allow(non_local_definitions)]
+
+use super::mem::{self, Word};
 
 use num::{FromPrimitive, Integer};
 use num_derive::FromPrimitive;
-use std::ops::{Add, Mul, Not};
+use serde_derive::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+use std::iter;
+use std::ops::Not;
 
-#[derive(Clone, Debug, Eq, PartialEq)]
-pub enum Instruction {
-    Arith(Opcode, Load, Load, Store),
-    CondJump(Opcode, Load, Load),
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum Instruction<W = isize> {
+    Arith(Opcode, Load<W>, Load<W>, Store),
+    CondJump(Opcode, Load<W>, Load<W>),
     Input(Store),
-    Output(Load),
-    SetRelBase(Load),
+    Output(Load<W>),
+    SetRelBase(Load<W>),
     Halt,
 }
 
-impl Instruction {
+impl<W> Instruction<W> {
     pub fn opcode(&self) -> Opcode {
         match self {
             Instruction::Arith(opcode, ..) => *opcode,
@@ -32,68 +39,266 @@ where
     I: IntoIterator<Item = &'a T>,
     T: 'a + Clone + Into<mem::Value>,
 {
+    /// Decodes the instruction at the start of `iter`, panicking if it is
+    /// malformed. Use `Instruction::try_decode` to handle errors instead.
     fn from(iter: I) -> Self {
-        let mut values = iter.into_iter().cloned().map(Into::into);
-        let first = values.next().unwrap();
-        let mut parameters = first.parameter_modes().zip(values);
+        let memory: mem::Memory = mem::Memory::from(iter.into_iter().cloned());
+        match Instruction::try_decode(&memory, mem::Address(0)) {
+            Ok(instruction) => instruction,
+            Err(err) => panic!("{}", err),
+        }
+    }
+}
 
-        let opcode = first.opcode();
-        match opcode {
+/// Formats the instruction in the syntax of `intcode::asm`.
+impl<W: Word> fmt::Display for Instruction<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.opcode().mnemonic())?;
+        match self {
+            Instruction::Arith(_, lhs, rhs, result) => write!(f, " {}, {}, {}", lhs, rhs, result),
+            Instruction::CondJump(_, x, addr) => write!(f, " {}, {}", x, addr),
+            Instruction::Input(store) => write!(f, " {}", store),
+            Instruction::Output(load) | Instruction::SetRelBase(load) => write!(f, " {}", load),
+            Instruction::Halt => Ok(()),
+        }
+    }
+}
+
+type Parameter<W> = (ParameterMode, mem::Value<W>);
+
+struct Decoder<'m, B: mem::MemoryBackend> {
+    memory: &'m mem::Memory<B>,
+    address: mem::Address,
+    modes: [Option<ParameterMode>; 3],
+    next_parameter: usize,
+}
+
+impl<'m, B: mem::MemoryBackend> Decoder<'m, B> {
+    fn error(&self, kind: DecodeErrorKind) -> DecodeError<B::Word> {
+        DecodeError {
+            address: self.address,
+            value: self.memory[self.address].clone(),
+            kind,
+        }
+    }
+
+    fn parameter(&mut self) -> Result<Parameter<B::Word>, DecodeError<B::Word>> {
+        let index = self.next_parameter;
+        self.next_parameter += 1;
+        let mode = self.modes[index]
+            .ok_or_else(|| self.error(DecodeErrorKind::BadParameterMode(index)))?;
+        let value = self.memory[mem::Address(self.address.0 + index + 1)].clone();
+        Ok((mode, value))
+    }
+
+    fn load(&mut self) -> Result<Load<B::Word>, DecodeError<B::Word>> {
+        let parameter = self.parameter()?;
+        Load::try_from(parameter).map_err(|kind| self.error(kind))
+    }
+
+    fn store(&mut self) -> Result<Store, DecodeError<B::Word>> {
+        let parameter = self.parameter()?;
+        Store::try_from(parameter).map_err(|kind| self.error(kind))
+    }
+}
+
+impl<W: Word> Instruction<W> {
+    /// Encodes the instruction as it would be laid out in memory, with the
+    /// parameter modes taken from its `Load` and `Store` operands.
+    pub fn encode(&self) -> Vec<mem::Value<W>> {
+        let parameters = match self.clone() {
+            Instruction::Arith(_, lhs, rhs, result) => vec![lhs.into(), rhs.into(), result.into()],
+            Instruction::CondJump(_, x, addr) => vec![x.into(), addr.into()],
+            Instruction::Input(store) => vec![store.into()],
+            Instruction::Output(load) | Instruction::SetRelBase(load) => vec![load.into()],
+            Instruction::Halt => vec![],
+        };
+        let modes = parameters
+            .iter()
+            .rev()
+            .fold(0, |modes, (mode, _): &(ParameterMode, mem::Value<W>)| {
+                modes * 10 + *mode as isize
+            });
+        let first = mem::Value::from(modes * 100 + self.opcode() as isize);
+        iter::once(first)
+            .chain(parameters.into_iter().map(|(_, value)| value))
+            .collect()
+    }
+
+    /// Decodes the instruction starting at `address`, checking its opcode,
+    /// its parameter modes and that all of its parameters are in memory.
+    pub fn try_decode<B: mem::MemoryBackend<Word = W>>(
+        memory: &mem::Memory<B>,
+        address: mem::Address,
+    ) -> Result<Self, DecodeError<W>> {
+        let first = &memory[address];
+        let mut decoder = Decoder {
+            memory,
+            address,
+            modes: [None; 3],
+            next_parameter: 0,
+        };
+        let opcode = first
+            .opcode()
+            .ok_or_else(|| decoder.error(DecodeErrorKind::UnknownOpcode))?;
+        if address.0 + opcode.len().0 as usize > memory.len() {
+            return Err(decoder.error(DecodeErrorKind::Truncated));
+        }
+        for (slot, mode) in decoder.modes.iter_mut().zip(first.parameter_modes()) {
+            *slot = mode;
+        }
+
+        let instruction = match opcode {
             Opcode::Add | Opcode::Multiply | Opcode::LessThan | Opcode::Equals => {
-                Instruction::Arith(
-                    opcode,
-                    parameters.next().unwrap().into(),
-                    parameters.next().unwrap().into(),
-                    parameters.next().unwrap().into(),
-                )
+                Instruction::Arith(opcode, decoder.load()?, decoder.load()?, decoder.store()?)
+            }
+            Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+                Instruction::CondJump(opcode, decoder.load()?, decoder.load()?)
             }
-            Opcode::JumpIfTrue | Opcode::JumpIfFalse => Instruction::CondJump(
-                opcode,
-                parameters.next().unwrap().into(),
-                parameters.next().unwrap().into(),
-            ),
-            Opcode::Input => Instruction::Input(parameters.next().unwrap().into()),
-            Opcode::Output => Instruction::Output(parameters.next().unwrap().into()),
-            Opcode::SetRelBase => Instruction::SetRelBase(parameters.next().unwrap().into()),
+            Opcode::Input => Instruction::Input(decoder.store()?),
+            Opcode::Output => Instruction::Output(decoder.load()?),
+            Opcode::SetRelBase => Instruction::SetRelBase(decoder.load()?),
             Opcode::Halt => Instruction::Halt,
+        };
+        Ok(instruction)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum DecodeErrorKind {
+    UnknownOpcode,
+    BadParameterMode(usize),
+    ImmediateStore,
+    NegativeAddress,
+    AddressTooLarge,
+    Truncated,
+}
+
+/// An error from decoding the instruction at `address`, whose first value
+/// (holding the opcode and parameter modes) is `value`.
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct DecodeError<W = isize> {
+    pub address: mem::Address,
+    pub value: mem::Value<W>,
+    pub kind: DecodeErrorKind,
+}
+
+impl<W: Word> fmt::Display for DecodeError<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "cannot decode instruction {} at address {}: ",
+            self.value.0, self.address.0
+        )?;
+        match self.kind {
+            DecodeErrorKind::UnknownOpcode => write!(f, "unknown opcode"),
+            DecodeErrorKind::BadParameterMode(index) => {
+                write!(f, "bad mode for parameter {}", index + 1)
+            }
+            DecodeErrorKind::ImmediateStore => write!(f, "write in immediate mode"),
+            DecodeErrorKind::NegativeAddress => write!(f, "negative address in position mode"),
+            DecodeErrorKind::AddressTooLarge => write!(f, "address or offset out of range"),
+            DecodeErrorKind::Truncated => write!(f, "instruction runs past end of memory"),
         }
     }
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
-pub enum Load {
+impl<W: Word> std::error::Error for DecodeError<W> {}
+
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum Load<W = isize> {
     Position(mem::Address),
-    Immediate(mem::Value),
-    Relative(mem::Address),
+    Immediate(mem::Value<W>),
+    Relative(mem::Offset),
 }
 
-impl From<(ParameterMode, mem::Value)> for Load {
-    fn from((mode, value): (ParameterMode, mem::Value)) -> Self {
+impl<W: Word> TryFrom<(ParameterMode, mem::Value<W>)> for Load<W> {
+    type Error = DecodeErrorKind;
+
+    fn try_from((mode, value): (ParameterMode, mem::Value<W>)) -> Result<Self, Self::Error> {
         match mode {
-            ParameterMode::Position => Load::Position(value.into()),
-            ParameterMode::Immediate => Load::Immediate(value),
-            ParameterMode::Relative => Load::Relative(value.into()),
+            ParameterMode::Position => Ok(Load::Position(position(&value)?)),
+            ParameterMode::Immediate => Ok(Load::Immediate(value)),
+            ParameterMode::Relative => Ok(Load::Relative(offset(value)?)),
+        }
+    }
+}
+
+impl<W: Word> From<Load<W>> for (ParameterMode, mem::Value<W>) {
+    fn from(load: Load<W>) -> Self {
+        match load {
+            Load::Position(address) => (ParameterMode::Position, (address.0 as isize).into()),
+            Load::Immediate(value) => (ParameterMode::Immediate, value),
+            Load::Relative(offset) => (ParameterMode::Relative, offset.0.into()),
+        }
+    }
+}
+
+impl<W: Word> fmt::Display for Load<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Load::Position(address) => write!(f, "{}", address.0),
+            Load::Immediate(value) => write!(f, "#{}", value.0),
+            Load::Relative(offset) => write!(f, "[{}]", offset.0),
         }
     }
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub enum Store {
     Position(mem::Address),
-    Relative(mem::Address),
+    Relative(mem::Offset),
 }
 
-impl From<(ParameterMode, mem::Value)> for Store {
-    fn from((mode, value): (ParameterMode, mem::Value)) -> Self {
+impl<W: Word> TryFrom<(ParameterMode, mem::Value<W>)> for Store {
+    type Error = DecodeErrorKind;
+
+    fn try_from((mode, value): (ParameterMode, mem::Value<W>)) -> Result<Self, Self::Error> {
         match mode {
-            ParameterMode::Position => Store::Position(value.into()),
-            ParameterMode::Immediate => panic!("cannot create store in immediate mode"),
-            ParameterMode::Relative => Store::Relative(value.into()),
+            ParameterMode::Position => Ok(Store::Position(position(&value)?)),
+            ParameterMode::Immediate => Err(DecodeErrorKind::ImmediateStore),
+            ParameterMode::Relative => Ok(Store::Relative(offset(value)?)),
+        }
+    }
+}
+
+impl<W: Word> From<Store> for (ParameterMode, mem::Value<W>) {
+    fn from(store: Store) -> Self {
+        match store {
+            Store::Position(address) => (ParameterMode::Position, (address.0 as isize).into()),
+            Store::Relative(offset) => (ParameterMode::Relative, offset.0.into()),
         }
     }
 }
 
-#[derive(Copy, Clone, Debug, Eq, FromPrimitive, PartialEq)]
+impl fmt::Display for Store {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Store::Position(address) => write!(f, "{}", address.0),
+            Store::Relative(offset) => write!(f, "[{}]", offset.0),
+        }
+    }
+}
+
+fn position<W: Word>(value: &mem::Value<W>) -> Result<mem::Address, DecodeErrorKind> {
+    mem::Address::try_from(value.clone()).map_err(|err| match err {
+        mem::AddressError::TooLarge => DecodeErrorKind::AddressTooLarge,
+        _ => DecodeErrorKind::NegativeAddress,
+    })
+}
+
+fn offset<W: Word>(value: mem::Value<W>) -> Result<mem::Offset, DecodeErrorKind> {
+    mem::Offset::try_from(value).map_err(|_| DecodeErrorKind::AddressTooLarge)
+}
+
+/// The length of the longest instruction, in values.
+pub const MAX_INSTRUCTION_LEN: usize = 4;
+
+/// The operation of an arithmetic opcode; see `Opcode::arith_fn`.
+pub type ArithFn<W = isize> =
+    fn(mem::Value<W>, mem::Value<W>, mem::Overflow) -> Option<mem::Value<W>>;
+
+#[derive(Copy, Clone, Debug, Deserialize, Eq, FromPrimitive, Hash, PartialEq, Serialize)]
 pub enum Opcode {
     Add = 1,
     Multiply = 2,
@@ -118,17 +323,51 @@ impl Opcode {
         }
     }
 
-    pub fn arith_fn(&self) -> fn(mem::Value, mem::Value) -> mem::Value {
+    pub fn mnemonic(&self) -> &'static str {
         match self {
-            Opcode::Add => mem::Value::add,
-            Opcode::Multiply => mem::Value::mul,
-            Opcode::LessThan => |x, y| x.lt(&y).into(),
-            Opcode::Equals => |x, y| x.eq(&y).into(),
+            Opcode::Add => "add",
+            Opcode::Multiply => "mul",
+            Opcode::Input => "in",
+            Opcode::Output => "out",
+            Opcode::JumpIfTrue => "jt",
+            Opcode::JumpIfFalse => "jf",
+            Opcode::LessThan => "lt",
+            Opcode::Equals => "eq",
+            Opcode::SetRelBase => "arb",
+            Opcode::Halt => "hlt",
+        }
+    }
+
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Opcode> {
+        let opcode = match mnemonic {
+            "add" => Opcode::Add,
+            "mul" => Opcode::Multiply,
+            "in" => Opcode::Input,
+            "out" => Opcode::Output,
+            "jt" => Opcode::JumpIfTrue,
+            "jf" => Opcode::JumpIfFalse,
+            "lt" => Opcode::LessThan,
+            "eq" => Opcode::Equals,
+            "arb" => Opcode::SetRelBase,
+            "hlt" => Opcode::Halt,
+            _ => return None,
+        };
+        Some(opcode)
+    }
+
+    /// Returns the operation of an arithmetic opcode, which yields `None`
+    /// if the result overflows and the overflow policy is to trap.
+    pub fn arith_fn<W: Word>(&self) -> ArithFn<W> {
+        match self {
+            Opcode::Add => mem::Value::add_with,
+            Opcode::Multiply => mem::Value::mul_with,
+            Opcode::LessThan => |x, y, _| Some(x.lt(&y).into()),
+            Opcode::Equals => |x, y, _| Some(x.eq(&y).into()),
             _ => panic!("opcode {:?} is not arithmetic", self),
         }
     }
 
-    pub fn cond_jump_fn(&self) -> fn(mem::Value) -> bool {
+    pub fn cond_jump_fn<W: Word>(&self) -> fn(mem::Value<W>) -> bool {
         match self {
             Opcode::JumpIfTrue => |x| x.into(),
             Opcode::JumpIfFalse => |x| bool::not(x.into()),
@@ -144,21 +383,22 @@ pub enum ParameterMode {
     Relative = 2,
 }
 
-impl mem::Value {
-    fn opcode(&self) -> Opcode {
-        let last_two_digits = self.0 as usize % 100;
-        Opcode::from_usize(last_two_digits).unwrap()
+impl<W: Word> mem::Value<W> {
+    fn opcode(&self) -> Option<Opcode> {
+        let value = self.0.to_isize().filter(|&value| value >= 0)?;
+        let last_two_digits = value as usize % 100;
+        Opcode::from_usize(last_two_digits)
     }
 
-    fn parameter_modes(&self) -> impl Iterator<Item = ParameterMode> {
-        let mut state = self.0 as usize / 100;
+    fn parameter_modes(&self) -> impl Iterator<Item = Option<ParameterMode>> {
+        let mut state = self.0.saturating_to_isize().max(0) as usize / 100;
         std::iter::from_fn(move || {
             if state == 0 {
-                Some(ParameterMode::Position)
+                Some(Some(ParameterMode::Position))
             } else {
                 let (div, rem) = state.div_rem(&10);
                 state = div;
-                Some(ParameterMode::from_usize(rem).unwrap())
+                Some(ParameterMode::from_usize(rem))
             }
         })
     }
@@ -168,16 +408,25 @@ impl mem::Value {
 mod test {
     use super::*;
 
+    use crate::intcode::Program;
+
+    use quickcheck::{Arbitrary, Gen};
+    use quickcheck_macros::quickcheck;
+    use rand::Rng;
+
     #[test]
     fn test_opcode_from_value() {
-        assert_eq!(mem::Value(1).opcode(), Opcode::Add);
-        assert_eq!(mem::Value(101).opcode(), Opcode::Add);
-        assert_eq!(mem::Value(1101).opcode(), Opcode::Add);
-        assert_eq!(mem::Value(10101).opcode(), Opcode::Add);
+        fn opcode(value: isize) -> Option<Opcode> {
+            mem::Value(value).opcode()
+        }
+        assert_eq!(opcode(1), Some(Opcode::Add));
+        assert_eq!(opcode(101), Some(Opcode::Add));
+        assert_eq!(opcode(1101), Some(Opcode::Add));
+        assert_eq!(opcode(10101), Some(Opcode::Add));
 
-        assert_eq!(mem::Value(108).opcode(), Opcode::Equals);
-        assert_eq!(mem::Value(99).opcode(), Opcode::Halt);
-        assert_eq!(mem::Value(199).opcode(), Opcode::Halt);
+        assert_eq!(opcode(108), Some(Opcode::Equals));
+        assert_eq!(opcode(99), Some(Opcode::Halt));
+        assert_eq!(opcode(199), Some(Opcode::Halt));
     }
 
     #[test]
@@ -186,7 +435,11 @@ mod test {
         let immediate = ParameterMode::Immediate;
         let relative = ParameterMode::Relative;
         fn modes(value: isize, len: usize) -> Vec<ParameterMode> {
-            mem::Value(value).parameter_modes().take(len).collect()
+            mem::Value(value)
+                .parameter_modes()
+                .take(len)
+                .map(Option::unwrap)
+                .collect()
         }
         assert_eq!(modes(1, 3), [position, position, position]);
         assert_eq!(modes(99, 3), [position, position, position]);
@@ -202,7 +455,7 @@ mod test {
     #[test]
     fn test_arith_instruction_from_memory() {
         assert_eq!(
-            Instruction::from(&[01101, 2, 6, 3]),
+            Instruction::from(&[1101, 2, 6, 3]),
             Instruction::Arith(
                 Opcode::Add,
                 Load::Immediate(2.into()),
@@ -220,7 +473,7 @@ mod test {
             )
         );
         assert_eq!(
-            Instruction::from(&[0107, -4, 3, 12]),
+            Instruction::from(&[107, -4, 3, 12]),
             Instruction::Arith(
                 Opcode::LessThan,
                 Load::Immediate((-4).into()),
@@ -287,4 +540,125 @@ mod test {
     fn test_halt_instruction_from_memory() {
         assert_eq!(Instruction::from(&[99, -1, 20]), Instruction::Halt);
     }
+
+    #[test]
+    fn test_decode_errors() {
+        fn decode_error(values: &[isize], address: usize) -> DecodeError {
+            let memory: mem::Memory = mem::Memory::from(values);
+            Instruction::try_decode(&memory, mem::Address(address)).unwrap_err()
+        }
+        assert_eq!(
+            decode_error(&[1, 0, 0, 0, 42], 4),
+            DecodeError {
+                address: mem::Address(4),
+                value: 42.into(),
+                kind: DecodeErrorKind::UnknownOpcode,
+            }
+        );
+        assert_eq!(decode_error(&[-1], 0).kind, DecodeErrorKind::UnknownOpcode);
+        assert_eq!(
+            decode_error(&[3101, 0, 0, 0], 0).kind,
+            DecodeErrorKind::BadParameterMode(1)
+        );
+        assert_eq!(
+            decode_error(&[10001, 0, 0, 0], 0).kind,
+            DecodeErrorKind::ImmediateStore
+        );
+        assert_eq!(
+            decode_error(&[103, 0], 0).kind,
+            DecodeErrorKind::ImmediateStore
+        );
+        assert_eq!(
+            decode_error(&[4, -1], 0).kind,
+            DecodeErrorKind::NegativeAddress
+        );
+        assert_eq!(
+            decode_error(&[99, 1002, 4, 3], 1).kind,
+            DecodeErrorKind::Truncated
+        );
+    }
+
+    #[test]
+    fn test_encode_instruction() {
+        let instruction = Instruction::Arith(
+            Opcode::Add,
+            Load::Immediate(2.into()),
+            Load::Relative((-6).into()),
+            Store::Position(3.into()),
+        );
+        assert_eq!(instruction.encode(), Program::from(&[2101, 2, -6, 3]).0);
+        let instruction = Instruction::Input(Store::Relative(0.into()));
+        assert_eq!(instruction.encode(), Program::from(&[203, 0]).0);
+        assert_eq!(Instruction::Halt.encode(), Program::from(&[99]).0);
+    }
+
+    const OPCODES: &[Opcode] = &[
+        Opcode::Add,
+        Opcode::Multiply,
+        Opcode::Input,
+        Opcode::Output,
+        Opcode::JumpIfTrue,
+        Opcode::JumpIfFalse,
+        Opcode::LessThan,
+        Opcode::Equals,
+        Opcode::SetRelBase,
+        Opcode::Halt,
+    ];
+
+    #[derive(Clone, Debug)]
+    struct Operands(Load, Load, Store);
+
+    fn arbitrary_operand<G: Gen>(g: &mut G) -> isize {
+        let size = g.size() as isize;
+        g.gen_range(-size, size + 1)
+    }
+
+    fn arbitrary_address<G: Gen>(g: &mut G) -> mem::Address {
+        mem::Address(arbitrary_operand(g).unsigned_abs())
+    }
+
+    fn arbitrary_load<G: Gen>(g: &mut G) -> Load {
+        match g.gen_range(0, 3) {
+            0 => Load::Position(arbitrary_address(g)),
+            1 => Load::Immediate(arbitrary_operand(g).into()),
+            _ => Load::Relative(arbitrary_operand(g).into()),
+        }
+    }
+
+    fn arbitrary_store<G: Gen>(g: &mut G) -> Store {
+        match g.gen_range(0, 2) {
+            0 => Store::Position(arbitrary_address(g)),
+            _ => Store::Relative(arbitrary_operand(g).into()),
+        }
+    }
+
+    impl Arbitrary for Operands {
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
+            Operands(arbitrary_load(g), arbitrary_load(g), arbitrary_store(g))
+        }
+    }
+
+    fn instruction(opcode: Opcode, Operands(x, y, z): Operands) -> Instruction {
+        match opcode {
+            Opcode::Add | Opcode::Multiply | Opcode::LessThan | Opcode::Equals => {
+                Instruction::Arith(opcode, x, y, z)
+            }
+            Opcode::JumpIfTrue | Opcode::JumpIfFalse => Instruction::CondJump(opcode, x, y),
+            Opcode::Input => Instruction::Input(z),
+            Opcode::Output => Instruction::Output(x),
+            Opcode::SetRelBase => Instruction::SetRelBase(x),
+            Opcode::Halt => Instruction::Halt,
+        }
+    }
+
+    #[quickcheck]
+    fn prop_decode_encode_round_trip(operands: Operands) -> bool {
+        OPCODES.iter().all(|&opcode| {
+            let instruction = instruction(opcode, operands.clone());
+            let encoded = instruction.encode();
+            let memory: mem::Memory = mem::Memory::from(encoded.iter().cloned());
+            let decoded = Instruction::try_decode(&memory, mem::Address(0));
+            encoded.len() == opcode.len().0 as usize && decoded == Ok(instruction)
+        })
+    }
 }
@@ -0,0 +1,327 @@
+//! An instruction-level profiler for `vm::Machine`.
+//!
+//! A `Profiler` is a tracer, so profiling is opt-in: attach one with
+//! `Machine::with_tracer`. It counts how often each address and each opcode
+//! is executed, how often each conditional jump is taken, and finds hot
+//! loops from the back edges taken at run time, meaning jumps to an address
+//! no later than the jump itself.
+
+use super::mem;
+use super::op::{Instruction, Opcode};
+use super::trace::{Completion, Trace, Tracer};
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::io;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct AddressProfile {
+    pub count: u64,
+    /// The instruction most recently executed at this address.
+    pub instruction: Instruction,
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct BranchProfile {
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+/// A loop found from a back edge from `latch` to `head`. `instructions` is
+/// the number of instructions executed at addresses from `head` to `latch`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Loop {
+    pub head: mem::Address,
+    pub latch: mem::Address,
+    pub iterations: u64,
+    pub instructions: u64,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Profiler {
+    pub total: u64,
+    pub addresses: HashMap<mem::Address, AddressProfile>,
+    pub opcodes: HashMap<Opcode, u64>,
+    pub branches: HashMap<mem::Address, BranchProfile>,
+    back_edges: HashMap<(mem::Address, mem::Address), u64>,
+}
+
+impl Tracer for Profiler {
+    fn trace(&mut self, trace: &Trace) {
+        if trace.completion != Completion::Retired {
+            return;
+        }
+        self.total += 1;
+        let instruction = trace.instruction;
+        self.addresses
+            .entry(trace.ins_ptr)
+            .and_modify(|profile| {
+                profile.count += 1;
+                profile.instruction = instruction;
+            })
+            .or_insert(AddressProfile {
+                count: 1,
+                instruction,
+            });
+        *self.opcodes.entry(instruction.opcode()).or_insert(0) += 1;
+
+        if let (Instruction::CondJump(opcode, _, _), [Some(x), Some(target)]) =
+            (instruction, trace.loads)
+        {
+            let branch = self.branches.entry(trace.ins_ptr).or_default();
+            if opcode.cond_jump_fn()(x) {
+                branch.taken += 1;
+                let head = mem::Address(target.0 as usize);
+                if head <= trace.ins_ptr {
+                    *self.back_edges.entry((head, trace.ins_ptr)).or_insert(0) += 1;
+                }
+            } else {
+                branch.not_taken += 1;
+            }
+        }
+    }
+}
+
+impl Profiler {
+    /// Returns the executed addresses, most executed first.
+    pub fn hot_addresses(&self) -> Vec<(mem::Address, AddressProfile)> {
+        let mut addresses = self
+            .addresses
+            .iter()
+            .map(|(&address, &profile)| (address, profile))
+            .collect::<Vec<_>>();
+        addresses.sort_by_key(|&(address, profile)| (Reverse(profile.count), address));
+        addresses
+    }
+
+    /// Returns the executed opcodes, most executed first.
+    pub fn hot_opcodes(&self) -> Vec<(Opcode, u64)> {
+        let mut opcodes = self
+            .opcodes
+            .iter()
+            .map(|(&opcode, &count)| (opcode, count))
+            .collect::<Vec<_>>();
+        opcodes.sort_by_key(|&(opcode, count)| (Reverse(count), opcode.mnemonic()));
+        opcodes
+    }
+
+    /// Returns the profile of every conditional jump, in address order.
+    pub fn branches(&self) -> Vec<(mem::Address, BranchProfile)> {
+        let mut branches = self
+            .branches
+            .iter()
+            .map(|(&address, &profile)| (address, profile))
+            .collect::<Vec<_>>();
+        branches.sort_by_key(|&(address, _)| address);
+        branches
+    }
+
+    /// Returns the loops found from back edges, those executing the most
+    /// instructions first.
+    pub fn hot_loops(&self) -> Vec<Loop> {
+        let mut loops = self
+            .back_edges
+            .iter()
+            .map(|(&(head, latch), &iterations)| Loop {
+                head,
+                latch,
+                iterations,
+                instructions: self
+                    .addresses
+                    .iter()
+                    .filter(|(&address, _)| head <= address && address <= latch)
+                    .map(|(_, profile)| profile.count)
+                    .sum(),
+            })
+            .collect::<Vec<_>>();
+        loops.sort_by_key(|l| (Reverse(l.instructions), l.head, l.latch));
+        loops
+    }
+
+    fn percent(&self, count: u64) -> f64 {
+        100.0 * count as f64 / self.total.max(1) as f64
+    }
+
+    /// Writes the profile as a set of plain-text tables.
+    pub fn write_text<W: io::Write>(&self, mut w: W) -> io::Result<()> {
+        writeln!(w, "{} instructions executed", self.total)?;
+
+        writeln!(w, "\naddress       count       %  instruction")?;
+        for (address, profile) in self.hot_addresses() {
+            let instruction = profile.instruction.to_string();
+            writeln!(
+                w,
+                "{:>7}  {:>10}  {:>6.2}  {}",
+                address.0,
+                profile.count,
+                self.percent(profile.count),
+                instruction
+            )?;
+        }
+
+        writeln!(w, "\nopcode        count       %")?;
+        for (opcode, count) in self.hot_opcodes() {
+            let percent = self.percent(count);
+            writeln!(
+                w,
+                "{:<7}  {:>10}  {:>6.2}",
+                opcode.mnemonic(),
+                count,
+                percent
+            )?;
+        }
+
+        writeln!(w, "\naddress       taken   not taken")?;
+        for (address, branch) in self.branches() {
+            writeln!(
+                w,
+                "{:>7}  {:>10}  {:>10}",
+                address.0, branch.taken, branch.not_taken
+            )?;
+        }
+
+        writeln!(w, "\n   head    latch  iterations  instructions       %")?;
+        for l in self.hot_loops() {
+            writeln!(
+                w,
+                "{:>7}  {:>7}  {:>10}  {:>12}  {:>6.2}",
+                l.head.0,
+                l.latch.0,
+                l.iterations,
+                l.instructions,
+                self.percent(l.instructions)
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes the profile as a single JSON object, with each table as an
+    /// array in the same order as the text report.
+    pub fn write_json<W: io::Write>(&self, mut w: W) -> io::Result<()> {
+        let addresses = self.hot_addresses().into_iter().map(|(address, profile)| {
+            format!(
+                r#"{{"address":{},"count":{},"instruction":"{}"}}"#,
+                address.0, profile.count, profile.instruction
+            )
+        });
+        let opcodes = self.hot_opcodes().into_iter().map(|(opcode, count)| {
+            format!(r#"{{"opcode":"{}","count":{}}}"#, opcode.mnemonic(), count)
+        });
+        let branches = self.branches().into_iter().map(|(address, branch)| {
+            format!(
+                r#"{{"address":{},"taken":{},"not_taken":{}}}"#,
+                address.0, branch.taken, branch.not_taken
+            )
+        });
+        let loops = self.hot_loops().into_iter().map(|l| {
+            format!(
+                r#"{{"head":{},"latch":{},"iterations":{},"instructions":{}}}"#,
+                l.head.0, l.latch.0, l.iterations, l.instructions
+            )
+        });
+        writeln!(
+            w,
+            r#"{{"total":{},"addresses":[{}],"opcodes":[{}],"branches":[{}],"loops":[{}]}}"#,
+            self.total,
+            addresses.collect::<Vec<_>>().join(","),
+            opcodes.collect::<Vec<_>>().join(","),
+            branches.collect::<Vec<_>>().join(","),
+            loops.collect::<Vec<_>>().join(",")
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::intcode::*;
+
+    // Outputs 3, 2, 1 from a countdown loop.
+    const COUNTDOWN: [isize; 13] = [4, 12, 1001, 12, -1, 12, 1005, 12, 0, 99, 0, 0, 3];
+
+    fn profile(program: &Program) -> Profiler {
+        let mut machine = Machine::default_io(program).with_tracer(Profiler::default());
+        assert_eq!(machine.run(), vm::Status::Halted);
+        machine.tracer
+    }
+
+    #[test]
+    fn test_counts() {
+        let profiler = profile(&Program::from(&COUNTDOWN));
+        assert_eq!(profiler.total, 10);
+        let addresses = profiler.hot_addresses();
+        let counts = addresses
+            .iter()
+            .map(|(address, profile)| (address.0, profile.count))
+            .collect::<Vec<_>>();
+        assert_eq!(counts, [(0, 3), (2, 3), (6, 3), (9, 1)]);
+        assert_eq!(addresses[3].1.instruction, Instruction::Halt);
+
+        assert_eq!(
+            profiler.hot_opcodes(),
+            [
+                (Opcode::Add, 3),
+                (Opcode::JumpIfTrue, 3),
+                (Opcode::Output, 3),
+                (Opcode::Halt, 1)
+            ]
+        );
+        assert_eq!(
+            profiler.branches(),
+            [(
+                mem::Address(6),
+                BranchProfile {
+                    taken: 2,
+                    not_taken: 1
+                }
+            )]
+        );
+        assert_eq!(
+            profiler.hot_loops(),
+            [Loop {
+                head: mem::Address(0),
+                latch: mem::Address(6),
+                iterations: 2,
+                instructions: 9,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_reports() {
+        let profiler = profile(&Program::from(&COUNTDOWN));
+        let mut text = vec![];
+        profiler.write_text(&mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        let lines = text.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "10 instructions executed");
+        assert_eq!(lines[3], "      0           3   30.00  out 12");
+        assert!(text.contains("\n      6           2           1\n"));
+        assert!(text.ends_with("      0        6           2             9   90.00\n"));
+
+        let mut json = vec![];
+        profiler.write_json(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.starts_with(
+            r#"{"total":10,"addresses":[{"address":0,"count":3,"instruction":"out 12"},"#
+        ));
+        assert!(json.ends_with(
+            r#""loops":[{"head":0,"latch":6,"iterations":2,"instructions":9}]}
+"#
+        ));
+    }
+
+    #[test]
+    fn test_day09_hot_loop() {
+        let program = include_str!("../../input/day09.in")
+            .parse::<Program>()
+            .unwrap();
+        let mut machine = Machine::default_io(&program).with_tracer(Profiler::default());
+        machine.input.queue.push_back(2.into());
+        assert_eq!(machine.run(), vm::Status::Halted);
+        let profiler = machine.tracer;
+        assert_eq!(profiler.total, machine.retired);
+        let hottest = profiler.hot_loops()[0];
+        assert!(hottest.instructions > profiler.total / 2);
+    }
+}
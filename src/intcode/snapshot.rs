@@ -0,0 +1,221 @@
+//! Saving and restoring the state of a `vm::Machine`.
+//!
+//! A snapshot is a short header, the bytes `ICSN` followed by the format
+//! version as a little-endian `u16`, and then the machine's memory, registers,
+//! status and I/O queues in bincode's variable-length integer encoding. The
+//! machine's tracer and engine are not saved; a restored machine gets
+//! default ones.
+
+use super::mem::MemoryBackend;
+use super::trace::Tracer;
+use super::vm::{Input, Machine, Output};
+
+use bincode::Options;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+const MAGIC: [u8; 4] = *b"ICSN";
+
+/// The version of the snapshot format written by `write`, and the only one
+/// `read` accepts.
+pub const VERSION: u16 = 3;
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    BadMagic,
+    UnsupportedVersion(u16),
+    Encoding(bincode::Error),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Io(err) => write!(f, "{}", err),
+            SnapshotError::BadMagic => write!(f, "not an intcode snapshot"),
+            SnapshotError::UnsupportedVersion(version) => {
+                write!(f, "unsupported snapshot version {}", version)
+            }
+            SnapshotError::Encoding(err) => write!(f, "invalid snapshot: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SnapshotError::Io(err) => Some(err),
+            SnapshotError::Encoding(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(err: io::Error) -> Self {
+        SnapshotError::Io(err)
+    }
+}
+
+impl From<bincode::Error> for SnapshotError {
+    fn from(err: bincode::Error) -> Self {
+        match *err {
+            bincode::ErrorKind::Io(err) => SnapshotError::Io(err),
+            _ => SnapshotError::Encoding(err),
+        }
+    }
+}
+
+fn options() -> impl Options {
+    bincode::DefaultOptions::new()
+}
+
+pub fn write<I, O, T, B, W>(
+    machine: &Machine<I, O, T, B>,
+    mut writer: W,
+) -> Result<(), SnapshotError>
+where
+    I: Input<B::Word> + Serialize,
+    O: Output<B::Word> + Serialize,
+    T: Tracer<B::Word>,
+    B: MemoryBackend + Serialize,
+    B::Word: Serialize,
+    W: Write,
+{
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    options().serialize_into(&mut writer, machine)?;
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn read<I, O, T, B, R>(mut reader: R) -> Result<Machine<I, O, T, B>, SnapshotError>
+where
+    I: Input<B::Word> + DeserializeOwned,
+    O: Output<B::Word> + DeserializeOwned,
+    T: Tracer<B::Word> + Default,
+    B: MemoryBackend + DeserializeOwned,
+    B::Word: DeserializeOwned,
+    R: Read,
+{
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(SnapshotError::BadMagic);
+    }
+    let mut version = [0; 2];
+    reader.read_exact(&mut version)?;
+    match u16::from_le_bytes(version) {
+        VERSION => Ok(options().deserialize_from(reader)?),
+        version => Err(SnapshotError::UnsupportedVersion(version)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::intcode::*;
+    use std::collections::HashMap;
+
+    fn day09() -> Program {
+        include_str!("../../input/day09.in")
+            .parse::<Program>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_resume_from_snapshot() {
+        let mut machine = Machine::default_io(&day09());
+        machine.input.queue.push_back(2.into());
+        assert_eq!(machine.run_with_fuel(1000), vm::Status::OutOfFuel);
+        let mut bytes = vec![];
+        write(&machine, &mut bytes).unwrap();
+        assert_eq!(&bytes[..6], b"ICSN\x03\x00");
+
+        let mut restored: Machine = read(bytes.as_slice()).unwrap();
+        assert_eq!(restored, machine);
+
+        assert_eq!(restored.run(), vm::Status::Halted);
+        assert_eq!(machine.run(), vm::Status::Halted);
+        assert_eq!(restored, machine);
+    }
+
+    #[test]
+    fn test_faulted_machine_round_trip() {
+        let mut machine = Machine::default_io(&Program::from(&[1105, 1, -4]));
+        assert!(matches!(machine.run(), vm::Status::Faulted(_)));
+        let mut bytes = vec![];
+        write(&machine, &mut bytes).unwrap();
+        assert_eq!(
+            read::<DefaultInput, DefaultOutput, _, mem::Dense, _>(&bytes[..]).unwrap(),
+            machine
+        );
+    }
+
+    #[test]
+    fn test_read_errors() {
+        let machine = Machine::default_io(&Program::from(&[99]));
+        let mut bytes = vec![];
+        write(&machine, &mut bytes).unwrap();
+        let read = |bytes: &[u8]| {
+            read::<DefaultInput, DefaultOutput, trace::NoTracer, mem::Dense, _>(bytes)
+        };
+
+        assert!(matches!(
+            read(b"ICSX\x03\x00"),
+            Err(SnapshotError::BadMagic)
+        ));
+        assert!(matches!(
+            read(b"ICSN\x02\x00"),
+            Err(SnapshotError::UnsupportedVersion(2))
+        ));
+        assert!(matches!(
+            read(&bytes[..bytes.len() - 1]),
+            Err(SnapshotError::Io(_))
+        ));
+        assert!(matches!(read(b"ICS"), Err(SnapshotError::Io(_))));
+
+        let mut corrupt = bytes.clone();
+        corrupt[6] = 0xff;
+        assert!(read(&corrupt).is_err());
+    }
+
+    #[test]
+    fn test_paged_page_lengths_are_checked() {
+        let mut machine: Machine<DefaultInput, DefaultOutput, trace::NoTracer, mem::Paged> =
+            Machine::new(&Program::from(&[99]));
+        machine.memory[mem::Address(2000)] = 5.into();
+        let mut bytes = vec![];
+        write(&machine, &mut bytes).unwrap();
+        let read = |bytes: &[u8]| {
+            read::<DefaultInput, DefaultOutput, trace::NoTracer, mem::Paged, _>(bytes)
+        };
+        assert_eq!(read(&bytes).unwrap(), machine);
+
+        // Replace the pages and length of the memory.
+        let memory = options().serialize(machine.memory.backend()).unwrap();
+        let position = bytes
+            .windows(memory.len())
+            .position(|window| window == memory.as_slice())
+            .unwrap();
+        let corrupt = |pages: &[(usize, Vec<mem::Value>)], len: usize| {
+            let pages = pages.iter().cloned().collect::<HashMap<_, _>>();
+            let mut corrupt = bytes[..position].to_vec();
+            corrupt.extend(options().serialize(&(pages, len)).unwrap());
+            corrupt.extend(&bytes[position + memory.len()..]);
+            corrupt
+        };
+
+        // A page that holds a single value.
+        let short_page = corrupt(&[(1, vec![mem::Value(5)])], 2001);
+        assert!(matches!(read(&short_page), Err(SnapshotError::Encoding(_))));
+
+        // A full page that starts past the end of memory.
+        let full_page = vec![mem::Value(0); 1024];
+        assert!(read(&corrupt(&[(1, full_page.clone())], 2001)).is_ok());
+        let past_len = corrupt(&[(2, full_page)], 2001);
+        assert!(matches!(read(&past_len), Err(SnapshotError::Encoding(_))));
+    }
+}
@@ -0,0 +1,355 @@
+//! Running machines as futures, many at a time on a single thread.
+//!
+//! Machines pass values over `pipe`s, which are unbounded queues shared
+//! within a thread. `run` turns a machine into a future that runs it like
+//! `Machine::run`, but returns `Poll::Pending` where the machine would block,
+//! and is woken again once something has been sent to its input. A machine
+//! also gives the other tasks a turn every `SLICE` instructions, so that one
+//! that never waits for input cannot hold up the rest.
+//!
+//! A machine's future finishes when it halts or faults, or when it is
+//! waiting on a pipe that every sender has gone from; its output is closed
+//! when it does. `Executor` polls these futures, or any others, until none
+//! of them can make progress.
+
+use super::mem::{Dense, MemoryBackend, Value};
+use super::trace::{NoTracer, Tracer};
+use super::vm::{Input, Machine, Output, Status};
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::future::{self, Future};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+
+/// How many instructions a machine runs before other tasks get a turn.
+const SLICE: u64 = 10_000;
+
+#[derive(Debug)]
+struct Pipe<W> {
+    queue: VecDeque<Value<W>>,
+    senders: usize,
+    waker: Option<Waker>,
+}
+
+/// Creates a pipe, returning its sending and receiving ends.
+pub fn pipe<W>() -> (PipeOutput<W>, PipeInput<W>) {
+    let pipe = Rc::new(RefCell::new(Pipe {
+        queue: VecDeque::new(),
+        senders: 1,
+        waker: None,
+    }));
+    let output = PipeOutput {
+        pipe: Some(pipe.clone()),
+    };
+    (output, PipeInput { pipe })
+}
+
+/// The receiving end of a pipe.
+#[derive(Debug)]
+pub struct PipeInput<W = isize> {
+    pipe: Rc<RefCell<Pipe<W>>>,
+}
+
+impl<W> PipeInput<W> {
+    /// Waits for a value, returning `None` once the pipe is empty and every
+    /// sender has gone.
+    pub async fn recv(&mut self) -> Option<Value<W>> {
+        future::poll_fn(|cx| self.poll_wait(cx)).await;
+        self.read_input()
+    }
+
+    /// Returns `Poll::Ready` once there is a value to read, or there never
+    /// will be, and otherwise arranges for the task to be woken when either
+    /// happens.
+    fn poll_wait(&self, cx: &mut Context) -> Poll<()> {
+        let mut pipe = self.pipe.borrow_mut();
+        if !pipe.queue.is_empty() || pipe.senders == 0 {
+            Poll::Ready(())
+        } else {
+            pipe.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+impl<W> Input<W> for PipeInput<W> {
+    fn read_input(&mut self) -> Option<Value<W>> {
+        self.pipe.borrow_mut().queue.pop_front()
+    }
+}
+
+/// A sending end of a pipe, which can be cloned to give it more senders.
+#[derive(Debug)]
+pub struct PipeOutput<W = isize> {
+    pipe: Option<Rc<RefCell<Pipe<W>>>>,
+}
+
+impl<W> PipeOutput<W> {
+    /// Stops sending, waking the receiver if this was the last sender.
+    /// Values written afterwards are dropped.
+    pub fn close(&mut self) {
+        if let Some(pipe) = self.pipe.take() {
+            let mut pipe = pipe.borrow_mut();
+            pipe.senders -= 1;
+            if pipe.senders == 0 {
+                if let Some(waker) = pipe.waker.take() {
+                    waker.wake();
+                }
+            }
+        }
+    }
+}
+
+impl<W> Clone for PipeOutput<W> {
+    fn clone(&self) -> Self {
+        if let Some(pipe) = &self.pipe {
+            pipe.borrow_mut().senders += 1;
+        }
+        PipeOutput {
+            pipe: self.pipe.clone(),
+        }
+    }
+}
+
+impl<W> Drop for PipeOutput<W> {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+impl<W> Output<W> for PipeOutput<W> {
+    fn write_output(&mut self, value: Value<W>) {
+        if let Some(pipe) = &self.pipe {
+            let mut pipe = pipe.borrow_mut();
+            pipe.queue.push_back(value);
+            if let Some(waker) = pipe.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    /// Always `false`, as values are sent as soon as they are written.
+    fn output_ready(&self) -> bool {
+        false
+    }
+}
+
+pub type PipeMachine<T = NoTracer, B = Dense> =
+    Machine<PipeInput<<B as MemoryBackend>::Word>, PipeOutput<<B as MemoryBackend>::Word>, T, B>;
+
+/// Runs `machine` until it halts, faults, or runs out of input for good,
+/// then closes its output and hands it back.
+pub async fn run<T, B>(mut machine: PipeMachine<T, B>) -> PipeMachine<T, B>
+where
+    T: Tracer<B::Word>,
+    B: MemoryBackend,
+{
+    future::poll_fn(|cx| poll_machine(&mut machine, cx)).await;
+    machine.output.close();
+    machine
+}
+
+fn poll_machine<T, B>(machine: &mut PipeMachine<T, B>, cx: &mut Context) -> Poll<Status<B::Word>>
+where
+    T: Tracer<B::Word>,
+    B: MemoryBackend,
+{
+    loop {
+        match machine.run_with_fuel(SLICE) {
+            Status::OutOfFuel => {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            Status::Blocked => {
+                if machine.input.poll_wait(cx).is_pending() {
+                    return Poll::Pending;
+                }
+                if machine.input.pipe.borrow().queue.is_empty() {
+                    return Poll::Ready(Status::Blocked);
+                }
+            }
+            status => return Poll::Ready(status),
+        }
+    }
+}
+
+/// The result of a future spawned on an `Executor`, once it has finished.
+#[derive(Debug)]
+pub struct Task<T> {
+    result: Rc<RefCell<Option<T>>>,
+}
+
+impl<T> Task<T> {
+    /// Takes the result, if the future has finished and it has not been
+    /// taken already.
+    pub fn take(&self) -> Option<T> {
+        self.result.borrow_mut().take()
+    }
+}
+
+type Ready = Arc<Mutex<VecDeque<usize>>>;
+
+struct TaskWaker {
+    task: usize,
+    ready: Ready,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.ready.lock().unwrap().push_back(self.task);
+    }
+}
+
+type LocalFuture<'a> = Pin<Box<dyn Future<Output = ()> + 'a>>;
+
+/// Polls futures on the current thread, each one whenever it has been woken.
+#[derive(Default)]
+pub struct Executor<'a> {
+    tasks: Vec<Option<(LocalFuture<'a>, Waker)>>,
+    ready: Ready,
+}
+
+impl<'a> Executor<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn spawn<F>(&mut self, future: F) -> Task<F::Output>
+    where
+        F: Future + 'a,
+        F::Output: 'a,
+    {
+        let result = Rc::new(RefCell::new(None));
+        let task = Task {
+            result: result.clone(),
+        };
+        let future = Box::pin(async move {
+            let output = future.await;
+            *result.borrow_mut() = Some(output);
+        });
+        let waker = Waker::from(Arc::new(TaskWaker {
+            task: self.tasks.len(),
+            ready: self.ready.clone(),
+        }));
+        waker.wake_by_ref();
+        self.tasks.push(Some((future, waker)));
+        task
+    }
+
+    /// Polls the futures until none of them has been woken, and returns how
+    /// many have not finished. Those are left waiting to be woken, by values
+    /// sent from outside the executor, say, before `run` is called again.
+    pub fn run(&mut self) -> usize {
+        loop {
+            let next = self.ready.lock().unwrap().pop_front();
+            let index = match next {
+                Some(index) => index,
+                None => break,
+            };
+            if let Some((future, waker)) = &mut self.tasks[index] {
+                let mut cx = Context::from_waker(waker);
+                if future.as_mut().poll(&mut cx).is_ready() {
+                    self.tasks[index] = None;
+                }
+            }
+        }
+        self.tasks.iter().filter(|task| task.is_some()).count()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::intcode::*;
+
+    /// Adds one to every value it reads, forever.
+    const INCREMENTER: &[isize] = &[3, 11, 1001, 11, 1, 11, 4, 11, 1105, 1, 0, 0];
+
+    fn spawn_chain(
+        executor: &mut Executor,
+        program: &Program,
+        input: PipeInput,
+        len: usize,
+    ) -> (Vec<Task<PipeMachine>>, PipeInput) {
+        let mut input = input;
+        let mut tasks = vec![];
+        for _ in 0..len {
+            let (output, next) = pipe();
+            let machine = Machine::default_io(program).with_io(input, output);
+            tasks.push(executor.spawn(run(machine)));
+            input = next;
+        }
+        (tasks, input)
+    }
+
+    #[test]
+    fn test_thousands_of_machines() {
+        let mut executor = Executor::new();
+        let (mut first, input) = pipe();
+        let program = Program::from(INCREMENTER);
+        let (tasks, mut last) = spawn_chain(&mut executor, &program, input, 2000);
+        first.write_output(0.into());
+        first.write_output(5.into());
+        assert_eq!(executor.run(), 2000);
+        assert_eq!(last.read_input(), Some(2000.into()));
+        assert_eq!(last.read_input(), Some(2005.into()));
+        assert_eq!(last.read_input(), None);
+
+        first.close();
+        assert_eq!(executor.run(), 0);
+        for task in tasks {
+            assert_eq!(task.take().unwrap().status, vm::Status::Blocked);
+        }
+    }
+
+    #[test]
+    fn test_halting_closes_output() {
+        let mut executor = Executor::new();
+        let source = Machine::default_io(&Program::from(&[104, 1, 104, 2, 99]));
+        let (output, input) = pipe();
+        let (_, empty) = pipe();
+        let source = executor.spawn(run(source.with_io(empty, output)));
+        let program = Program::from(INCREMENTER);
+        let (tasks, mut last) = spawn_chain(&mut executor, &program, input, 3);
+        let sum = executor.spawn(async move {
+            let mut sum = 0;
+            while let Some(value) = last.recv().await {
+                sum += value.0;
+            }
+            sum
+        });
+        assert_eq!(executor.run(), 0);
+        assert_eq!(sum.take(), Some(4 + 5));
+        assert_eq!(source.take().unwrap().status, vm::Status::Halted);
+        for task in tasks {
+            let machine = task.take().unwrap();
+            assert_eq!(machine.status, vm::Status::Blocked);
+            assert_eq!(machine.retired, 2 * 4);
+        }
+    }
+
+    #[test]
+    fn test_busy_machines_take_turns() {
+        // Counts down from 100000 before it outputs anything.
+        let busy = Program::from(&[1001, 10, -1, 10, 1005, 10, 0, 104, 7, 99, 100_000]);
+        let quick = Program::from(&[104, 8, 99]);
+        let mut executor = Executor::new();
+        let (output, mut result) = pipe();
+        for program in &[busy, quick] {
+            let (_, empty) = pipe();
+            let machine = Machine::default_io(program).with_io(empty, output.clone());
+            executor.spawn(run(machine));
+        }
+        drop(output);
+        assert_eq!(executor.run(), 0);
+        assert_eq!(result.read_input(), Some(8.into()));
+        assert_eq!(result.read_input(), Some(7.into()));
+    }
+}
@@ -1,167 +1,368 @@
-mod test_day02 {
-    use crate::intcode::*;
+/// Defines a module of the tests in `test/machine.rs` for each memory
+/// backend and engine, so that all of them are held to the same behaviour.
+macro_rules! machine_tests {
+    ($($name:ident: $backend:ty => $engine:expr,)*) => {$(
+        mod $name {
+            use crate::intcode::*;
 
-    #[test]
-    fn test_step() {
-        let program = Program::from(&[1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
-        let mut machine = Machine::default_io(&program);
-        machine.step();
-        assert_eq!(
-            machine.memory,
-            [1, 9, 10, 70, 2, 3, 11, 0, 99, 30, 40, 50].iter().into()
-        );
-        machine.step();
-        assert_eq!(
-            machine.memory,
-            [3500, 9, 10, 70, 2, 3, 11, 0, 99, 30, 40, 50].iter().into()
-        );
-        let status = machine.step();
-        assert_eq!(status, vm::Status::Halted);
-    }
-
-    #[test]
-    fn test_begin_end_states() {
-        let states: &[(Program, mem::Memory)] = &[
-            (
-                [1, 0, 0, 0, 99].iter().into(),
-                [2, 0, 0, 0, 99].iter().into(),
-            ),
-            (
-                [2, 3, 0, 3, 99].iter().into(),
-                [2, 3, 0, 6, 99].iter().into(),
-            ),
-            (
-                [2, 4, 4, 5, 99, 0].iter().into(),
-                [2, 4, 4, 5, 99, 9801].iter().into(),
-            ),
-            (
-                [1, 1, 1, 4, 99, 5, 6, 0, 99].iter().into(),
-                [30, 1, 1, 4, 2, 5, 6, 0, 99].iter().into(),
-            ),
-        ];
-        for (program, memory) in states {
-            let mut machine = Machine::default_io(program);
-            let status = machine.run();
-            assert_eq!(status, vm::Status::Halted);
-            assert_eq!(&machine.memory, memory);
+            type Backend = $backend;
+
+            type TestMachine = Machine<DefaultInput, DefaultOutput, trace::NoTracer, Backend>;
+
+            fn new_machine(program: &Program) -> TestMachine {
+                let mut machine = Machine::new(program);
+                machine.engine = $engine;
+                machine
+            }
+
+            include!("test/machine.rs");
         }
-    }
+    )*};
 }
 
-mod test_day05 {
+machine_tests! {
+    dense: mem::Dense => vm::Engine::Reference,
+    paged: mem::Paged => vm::Engine::Reference,
+    copy_on_write: mem::CopyOnWrite => vm::Engine::Reference,
+    fast: mem::Dense => vm::Engine::Fast,
+    fast_paged: mem::Paged => vm::Engine::Fast,
+}
+
+mod test_parse {
     use crate::intcode::*;
 
-    fn machine_io_test<'a>(
-        program: &Program,
-        expected: impl IntoIterator<Item = &'a (isize, isize)>,
-    ) {
-        for (input, output) in expected.into_iter() {
-            let mut machine = Machine::default_io(program);
-            machine.input.queue.push_back(input.into());
-            let status = machine.run();
-            assert_eq!(status, vm::Status::Halted);
-            assert_eq!(machine.output.buffer, [output.into()]);
-        }
+    #[test]
+    fn test_parse_program() {
+        let program = "1,0,0,3,99\n".parse::<Program>().unwrap();
+        assert_eq!(program, Program::from(&[1, 0, 0, 3, 99]));
+
+        let program = " 1, -2 ,\n 3,\t99 \n".parse::<Program>().unwrap();
+        assert_eq!(program, Program::from(&[1, -2, 3, 99]));
     }
 
     #[test]
-    fn test_eq_8_position_mode() {
-        let program = Program::from(&[3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8]);
-        let expected = &[(8, 1), (7, 0)];
-        machine_io_test(&program, expected);
+    fn test_parse_comments() {
+        let source = "# header, with commas\n1,0,0,3, # add\n99 # halt\n";
+        let program = source.parse::<Program>().unwrap();
+        assert_eq!(program, Program::from(&[1, 0, 0, 3, 99]));
+        assert_eq!(Program::parse_strict(source), Ok(program));
     }
 
     #[test]
-    fn test_lt_8_position_mode() {
-        let program = Program::from(&[3, 9, 7, 9, 10, 9, 4, 9, 99, -1, 8]);
-        let expected = &[(5, 1), (8, 0), (10, 0)];
-        machine_io_test(&program, expected);
+    fn test_parse_invalid_token() {
+        let err = "1,0,x,3".parse::<Program>().unwrap_err();
+        assert_eq!(err.token, "x");
+        assert_eq!(err.index, 2);
+        assert_eq!(err.offset, 4);
+        assert!(matches!(err.kind, ProgramParseErrorKind::Invalid(_)));
+
+        let err = "1, 2,\n  3 4".parse::<Program>().unwrap_err();
+        assert_eq!(err.token, "3 4");
+        assert_eq!(err.index, 2);
+        assert_eq!(err.offset, 8);
     }
+
     #[test]
-    fn test_eq_8_immediate_mode() {
-        let program = Program::from(&[3, 3, 1108, -1, 8, 3, 4, 3, 99]);
-        let expected = &[(8, 1), (7, 0)];
-        machine_io_test(&program, expected);
+    fn test_parse_index_skips_empty_tokens() {
+        let err = "1,,2,x".parse::<Program>().unwrap_err();
+        assert_eq!(err.token, "x");
+        assert_eq!(err.index, 2);
+        assert_eq!(err.offset, 5);
     }
 
     #[test]
-    fn test_lt_8_immediate_mode() {
-        let program = Program::from(&[3, 3, 1107, -1, 8, 3, 4, 3, 99]);
-        let expected = &[(5, 1), (8, 0), (12, 0)];
-        machine_io_test(&program, expected);
+    fn test_parse_strict_empty_token() {
+        assert_eq!("1,,2,".parse::<Program>(), Ok(Program::from(&[1, 2])));
+        let err = Program::<isize>::parse_strict("1,,2").unwrap_err();
+        assert_eq!(err.kind, ProgramParseErrorKind::Empty);
+        assert_eq!(err.index, 1);
+        assert_eq!(err.offset, 2);
     }
+}
+
+mod test_backends {
+    use crate::intcode::mem::*;
+    use crate::intcode::*;
 
     #[test]
-    fn test_nz_position_mode() {
-        let program = Program::from(&[3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9]);
-        let expected = &[(0, 0), (1, 1), (5, 1)];
-        machine_io_test(&program, expected);
+    fn test_paged_far_write() {
+        let mut memory: Memory<Paged> = [1, 2, 3].iter().into();
+        memory[Address(1_000_000_000)] = 7.into();
+        assert_eq!(memory.len(), 1_000_000_001);
+        assert_eq!(memory[Address(1_000_000_000)], 7.into());
+        assert_eq!(memory[Address(999_999_999)], 0.into());
+        assert_eq!(memory[Address(2)], 3.into());
+
+        memory.truncate(2);
+        assert_eq!(memory.len(), 2);
+        assert_eq!(memory[Address(2)], 0.into());
+        assert_eq!(memory, [1, 2].iter().into());
     }
 
     #[test]
-    fn test_nz_immediate_mode() {
-        let program = Program::from(&[3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1]);
-        let expected = &[(0, 0), (1, 1), (39, 1)];
-        machine_io_test(&program, expected);
+    fn test_paged_equality_ignores_allocation() {
+        let mut memory: Memory<Paged> = [0; 5000].iter().into();
+        memory[Address(4000)] = 0.into();
+        assert_eq!(memory, [0; 5000].iter().into());
+        memory[Address(4000)] = 1.into();
+        assert_ne!(memory, [0; 5000].iter().into());
     }
 
     #[test]
-    fn test_big_input() {
-        let program = Program::from(
-            [
-                3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36,
-                98, 0, 0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000,
-                1, 20, 4, 20, 1105, 1, 46, 98, 99,
-            ]
-            .iter(),
-        );
-        let expected = &[(7, 999), (8, 1000), (9, 1001)];
-        machine_io_test(&program, expected);
+    fn test_copy_on_write_shares_until_written() {
+        let program = include_str!("../../input/day09.in")
+            .parse::<Program>()
+            .unwrap();
+        let mut machine =
+            Machine::<DefaultInput, DefaultOutput, trace::NoTracer, CopyOnWrite>::new(&program);
+        let fork = machine.clone();
+        assert!(machine.memory.backend().is_shared());
+        machine.input.queue.push_back(1.into());
+        machine.run();
+        assert!(!machine.memory.backend().is_shared());
+        assert!(!fork.memory.backend().is_shared());
+        assert_eq!(fork.memory, (&program).into());
     }
 }
 
-mod test_day09 {
+mod test_words {
     use crate::intcode::*;
 
-    use radixal::IntoDigits;
+    use num::BigInt;
+    use std::convert::TryFrom;
+
+    type WordMachine<W> =
+        Machine<DefaultInput<W>, DefaultOutput<W>, trace::NoTracer, mem::Dense<W>>;
+
+    fn run<W: mem::Word>(source: &str, input: isize) -> Result<Vec<String>, vm::Status<W>> {
+        let program = source.parse::<Program<W>>().unwrap();
+        let mut machine = WordMachine::new(&program);
+        machine.input.queue.push_back(input.into());
+        match machine.run() {
+            vm::Status::Halted => Ok(machine
+                .output
+                .buffer
+                .iter()
+                .map(|v| v.0.to_string())
+                .collect()),
+            status => Err(status),
+        }
+    }
 
     #[test]
-    fn test_set_rel_base() {
-        let program = Program::from(&[109, 42, 99]);
-        let mut machine = Machine::default_io(&program);
-        machine.run();
-        assert_eq!(machine.memory.rel_base, 42.into());
+    fn test_puzzles_on_every_word() {
+        let day05 = include_str!("../../input/day05.in");
+        let day09 = include_str!("../../input/day09.in");
+        for &(source, input) in &[(day05, 1), (day05, 5), (day09, 1), (day09, 2)] {
+            let expected = run::<isize>(source, input).unwrap();
+            assert_eq!(run::<i64>(source, input), Ok(expected.clone()));
+            assert_eq!(run::<i128>(source, input), Ok(expected.clone()));
+            assert_eq!(run::<BigInt>(source, input), Ok(expected));
+        }
     }
 
     #[test]
-    fn test_quine() {
-        let quine = &[
-            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
-        ];
-        let program = Program::from(quine);
-        let mut machine = Machine::default_io(&program);
-        machine.run();
+    fn test_wide_words() {
+        // Squares 2^62, which overflows an i64 but not an i128.
+        let source = "1102,4611686018427387904,4611686018427387904,7,4,7,99,0";
+        assert!(matches!(run::<i64>(source, 0), Err(vm::Status::Faulted(_))));
+        let square = "21267647932558653966460912964485513216";
+        assert_eq!(run::<i128>(source, 0), Ok(vec![square.to_owned()]));
+        assert_eq!(run::<BigInt>(source, 0), Ok(vec![square.to_owned()]));
+
+        // 2^130 does not fit in an i128 at all, so only a BigInt can run it.
+        let source = "1102,1361129467683753853853498429727072845824,\
+                      1361129467683753853853498429727072845824,7,4,7,99,0";
+        assert!(source.parse::<Program<i128>>().is_err());
+        let square = num::pow(BigInt::from(2), 260).to_string();
+        assert_eq!(run::<BigInt>(source, 0), Ok(vec![square]));
+    }
+
+    #[test]
+    fn test_huge_addresses_do_not_alias() {
+        // 2^100 and 2^101 both used to saturate to `isize::MAX`.
+        let huge = num::pow(BigInt::from(2), 100);
+        assert_eq!(
+            mem::Address::try_from(mem::Value(huge.clone())),
+            Err(mem::AddressError::TooLarge)
+        );
         assert_eq!(
-            machine.output.buffer,
-            quine.iter().map(Into::into).collect::<Vec<mem::Value>>()
+            mem::Offset::try_from(mem::Value(huge.clone())),
+            Err(mem::AddressError::TooLarge)
         );
+        let source = format!("1101,7,0,{},1101,9,0,{},99", &huge, &huge * 2);
+        let too_large = |status| {
+            matches!(
+                status,
+                Err(vm::Status::Faulted(vm::Fault {
+                    ins_ptr: mem::Address(0),
+                    cause: vm::FaultCause::Decode(op::DecodeError {
+                        kind: op::DecodeErrorKind::AddressTooLarge,
+                        ..
+                    }),
+                    ..
+                }))
+            )
+        };
+        assert!(too_large(run::<BigInt>(&source, 0)));
+        assert!(too_large(run::<BigInt>(&format!("204,{},99", huge), 0)));
+
+        // A relative base that does not fit in an `isize` overflows.
+        assert!(matches!(
+            run::<BigInt>(&format!("109,{},99", huge), 0),
+            Err(vm::Status::Faulted(vm::Fault {
+                cause: vm::FaultCause::Overflow,
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_huge_jump_faults() {
+        let source = "1105,1,1267650600228229401496703205376";
+        let target = "1267650600228229401496703205376".parse().unwrap();
+        assert!(matches!(
+            run::<BigInt>(source, 0),
+            Err(vm::Status::Faulted(vm::Fault {
+                cause: vm::FaultCause::JumpOutOfRange(mem::Value(value)),
+                ..
+            })) if value == target
+        ));
+    }
+}
+
+mod test_engines {
+    use crate::intcode::*;
+
+    fn run_both(program: &Program, input: isize, fuel: u64) {
+        let mut reference = Machine::default_io(program);
+        let mut fast = reference.clone();
+        fast.engine = vm::Engine::Fast;
+        for machine in [&mut reference, &mut fast].iter_mut() {
+            machine.input.queue.push_back(input.into());
+            while machine.run_with_fuel(fuel) == vm::Status::OutOfFuel {}
+        }
+        fast.engine = vm::Engine::Reference;
+        assert_eq!(fast, reference);
     }
 
     #[test]
-    fn test_16_digit_num() {
-        let program = Program::from(&[1102, 34915192, 34915192, 7, 4, 7, 99, 0]);
+    fn test_engines_agree() {
+        let day09 = include_str!("../../input/day09.in").parse().unwrap();
+        run_both(&day09, 1, 7);
+        run_both(&day09, 2, 100_000);
+        let day05 = include_str!("../../input/day05.in").parse().unwrap();
+        run_both(&day05, 5, 3);
+    }
+
+    #[test]
+    fn test_overwritten_code_runs_on_reference_interpreter() {
+        // Outputs the first five values in memory by incrementing the
+        // address operand of its own output instruction.
+        let program = Program::from(&[4, 0, 1001, 1, 1, 1, 1007, 1, 5, 15, 1005, 15, 0, 99, 0, 0]);
+        let mut reference = Machine::default_io(&program);
+        let mut fast = reference.clone();
+        fast.engine = vm::Engine::Fast;
+        assert_eq!(reference.run(), vm::Status::Halted);
+        assert_eq!(fast.run(), vm::Status::Halted);
+        let expected = [4, 1, 1001, 1, 1]
+            .iter()
+            .map(Into::into)
+            .collect::<Vec<_>>();
+        assert_eq!(fast.output.buffer, expected);
+        fast.engine = vm::Engine::Reference;
+        assert_eq!(fast, reference);
+    }
+
+    #[test]
+    fn test_writes_between_runs() {
+        let program = Program::from(&[3, 20, 1001, 20, 1, 21, 4, 21, 1105, 1, 0]);
         let mut machine = Machine::default_io(&program);
-        machine.run();
-        let output = machine.output.buffer;
-        let value = output[0].0 as usize;
-        assert_eq!(value.into_decimal_digits().count(), 16);
+        machine.engine = vm::Engine::Fast;
+        machine.input.queue.push_back(5.into());
+        assert_eq!(machine.run(), vm::Status::Blocked);
+        machine.memory[mem::Address(4)] = 10.into();
+        machine.input.queue.push_back(5.into());
+        assert_eq!(machine.run(), vm::Status::Blocked);
+        assert_eq!(machine.output.buffer, [6.into(), 15.into()]);
+    }
+}
+
+mod test_transpile {
+    use crate::intcode::*;
+
+    mod day09 {
+        use crate::intcode;
+        include!(concat!(env!("OUT_DIR"), "/day09.rs"));
+    }
+
+    mod self_modifying {
+        use crate::intcode;
+        include!("test/self_modifying.rs");
+    }
+
+    // The programs of the known-answer tests in `test/machine.rs`, listed in
+    // `test/known_answers.in`, transpiled by the build script.
+    include!(concat!(env!("OUT_DIR"), "/known_answers.rs"));
+
+    type Run = fn(&mut Machine) -> vm::Status;
+
+    const SELF_MODIFYING: &str = "4,0,1001,1,1,1,1007,1,5,15,1005,15,0,99,0,0";
+
+    #[test]
+    fn test_generated_code_is_up_to_date() {
+        let program = SELF_MODIFYING.parse().unwrap();
+        let generated = include_str!("test/self_modifying.rs");
+        assert_eq!(transpile::transpile(&program), generated);
+    }
+
+    fn run_both(source: &str, input: &[isize], run: Run) -> Machine {
+        let program = source.parse().unwrap();
+        let mut native = Machine::default_io(&program);
+        let mut interpreted = Machine::default_io(&program);
+        assert_eq!(run(&mut native), interpreted.run());
+        for &value in input {
+            native.input.queue.push_back(value.into());
+            interpreted.input.queue.push_back(value.into());
+        }
+        assert_eq!(run(&mut native), interpreted.run());
+        assert_eq!(native, interpreted);
+        native
     }
 
     #[test]
-    fn test_middle_num() {
-        let program = Program::from(&[104, 1125899906842624, 99]);
+    fn test_native_code_matches_interpreter() {
+        let day09 = include_str!("../../input/day09.in");
+        for &input in &[1, 2] {
+            let machine = run_both(day09, &[input], day09::run);
+            assert_eq!(machine.status, vm::Status::Halted);
+        }
+    }
+
+    #[test]
+    fn test_known_answers_match_interpreter() {
+        assert_eq!(KNOWN_ANSWERS.len(), 16);
+        for &(source, run) in KNOWN_ANSWERS {
+            for &input in &[-1, 0, 1, 5, 7, 8, 9, 12, 39] {
+                let machine = run_both(source, &[input], run);
+                assert_eq!(machine.status, vm::Status::Halted, "{}", source);
+            }
+        }
+    }
+
+    #[test]
+    fn test_code_writes_fall_back_to_interpreter() {
+        let machine = run_both(SELF_MODIFYING, &[], self_modifying::run);
+        let expected = [4, 1, 1001, 1, 1]
+            .iter()
+            .map(Into::into)
+            .collect::<Vec<_>>();
+        assert_eq!(machine.output.buffer, expected);
+
+        let program = include_str!("../../input/day09.in").parse().unwrap();
         let mut machine = Machine::default_io(&program);
-        machine.run();
-        assert_eq!(machine.output.buffer[0], 1125899906842624.into());
+        machine.memory[mem::Address(0)] = 99.into();
+        assert_eq!(day09::run(&mut machine), vm::Status::Halted);
+        assert_eq!(machine.retired, 1);
     }
 }
@@ -0,0 +1,541 @@
+mod test_day02 {
+    use super::{new_machine, Backend};
+    use crate::intcode::*;
+
+    #[test]
+    fn test_step() {
+        let program = Program::from(&[1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
+        let mut machine = new_machine(&program);
+        machine.step();
+        assert_eq!(
+            machine.memory,
+            [1, 9, 10, 70, 2, 3, 11, 0, 99, 30, 40, 50].iter().into()
+        );
+        machine.step();
+        assert_eq!(
+            machine.memory,
+            [3500, 9, 10, 70, 2, 3, 11, 0, 99, 30, 40, 50].iter().into()
+        );
+        let status = machine.step();
+        assert_eq!(status, vm::Status::Halted);
+    }
+
+    #[test]
+    fn test_begin_end_states() {
+        let states: &[(Program, mem::Memory<Backend>)] = &[
+            (
+                [1, 0, 0, 0, 99].iter().into(),
+                [2, 0, 0, 0, 99].iter().into(),
+            ),
+            (
+                [2, 3, 0, 3, 99].iter().into(),
+                [2, 3, 0, 6, 99].iter().into(),
+            ),
+            (
+                [2, 4, 4, 5, 99, 0].iter().into(),
+                [2, 4, 4, 5, 99, 9801].iter().into(),
+            ),
+            (
+                [1, 1, 1, 4, 99, 5, 6, 0, 99].iter().into(),
+                [30, 1, 1, 4, 2, 5, 6, 0, 99].iter().into(),
+            ),
+        ];
+        for (program, memory) in states {
+            let mut machine = new_machine(program);
+            let status = machine.run();
+            assert_eq!(status, vm::Status::Halted);
+            assert_eq!(&machine.memory, memory);
+        }
+    }
+}
+
+mod test_day05 {
+    use super::new_machine;
+    use crate::intcode::*;
+
+    fn machine_io_test<'a>(
+        program: &Program,
+        expected: impl IntoIterator<Item = &'a (isize, isize)>,
+    ) {
+        for (input, output) in expected.into_iter() {
+            let mut machine = new_machine(program);
+            machine.input.queue.push_back(input.into());
+            let status = machine.run();
+            assert_eq!(status, vm::Status::Halted);
+            assert_eq!(machine.output.buffer, [output.into()]);
+        }
+    }
+
+    #[test]
+    fn test_eq_8_position_mode() {
+        let program = Program::from(&[3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8]);
+        let expected = &[(8, 1), (7, 0)];
+        machine_io_test(&program, expected);
+    }
+
+    #[test]
+    fn test_lt_8_position_mode() {
+        let program = Program::from(&[3, 9, 7, 9, 10, 9, 4, 9, 99, -1, 8]);
+        let expected = &[(5, 1), (8, 0), (10, 0)];
+        machine_io_test(&program, expected);
+    }
+    #[test]
+    fn test_eq_8_immediate_mode() {
+        let program = Program::from(&[3, 3, 1108, -1, 8, 3, 4, 3, 99]);
+        let expected = &[(8, 1), (7, 0)];
+        machine_io_test(&program, expected);
+    }
+
+    #[test]
+    fn test_lt_8_immediate_mode() {
+        let program = Program::from(&[3, 3, 1107, -1, 8, 3, 4, 3, 99]);
+        let expected = &[(5, 1), (8, 0), (12, 0)];
+        machine_io_test(&program, expected);
+    }
+
+    #[test]
+    fn test_nz_position_mode() {
+        let program = Program::from(&[3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9]);
+        let expected = &[(0, 0), (1, 1), (5, 1)];
+        machine_io_test(&program, expected);
+    }
+
+    #[test]
+    fn test_nz_immediate_mode() {
+        let program = Program::from(&[3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1]);
+        let expected = &[(0, 0), (1, 1), (39, 1)];
+        machine_io_test(&program, expected);
+    }
+
+    #[test]
+    fn test_big_input() {
+        let program = Program::from(
+            [
+                3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36,
+                98, 0, 0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000,
+                1, 20, 4, 20, 1105, 1, 46, 98, 99,
+            ]
+            .iter(),
+        );
+        let expected = &[(7, 999), (8, 1000), (9, 1001)];
+        machine_io_test(&program, expected);
+    }
+}
+
+mod test_day09 {
+    use super::new_machine;
+    use crate::intcode::*;
+
+    use radixal::IntoDigits;
+
+    #[test]
+    fn test_set_rel_base() {
+        let program = Program::from(&[109, 42, 99]);
+        let mut machine = new_machine(&program);
+        machine.run();
+        assert_eq!(machine.memory.rel_base, 42.into());
+    }
+
+    #[test]
+    fn test_quine() {
+        let quine = &[
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+        ];
+        let program = Program::from(quine);
+        let mut machine = new_machine(&program);
+        machine.run();
+        assert_eq!(
+            machine.output.buffer,
+            quine.iter().map(Into::into).collect::<Vec<mem::Value>>()
+        );
+    }
+
+    #[test]
+    fn test_16_digit_num() {
+        let program = Program::from(&[1102, 34915192, 34915192, 7, 4, 7, 99, 0]);
+        let mut machine = new_machine(&program);
+        machine.run();
+        let output = machine.output.buffer;
+        let value = output[0].0 as usize;
+        assert_eq!(value.into_decimal_digits().count(), 16);
+    }
+
+    #[test]
+    fn test_middle_num() {
+        let program = Program::from(&[104, 1125899906842624, 99]);
+        let mut machine = new_machine(&program);
+        machine.run();
+        assert_eq!(machine.output.buffer[0], 1125899906842624.into());
+    }
+}
+
+mod test_faults {
+    use super::new_machine;
+    use crate::intcode::*;
+
+    fn run_to_fault(program: &[isize]) -> vm::Fault {
+        let mut machine = new_machine(&Program::from(program));
+        match machine.run() {
+            vm::Status::Faulted(fault) => fault,
+            status => panic!("expected fault, got {:?}", status),
+        }
+    }
+
+    #[test]
+    fn test_decode_fault() {
+        let program = Program::from(&[1101, 40, 2, 4, 99]);
+        let mut machine = new_machine(&program);
+        let status = machine.run();
+        let fault = vm::Fault {
+            ins_ptr: mem::Address(4),
+            instruction: None,
+            cause: vm::FaultCause::Decode(op::DecodeError {
+                address: mem::Address(4),
+                value: 42.into(),
+                kind: op::DecodeErrorKind::UnknownOpcode,
+            }),
+        };
+        assert_eq!(status, vm::Status::Faulted(fault));
+        assert_eq!(machine.step(), status);
+        assert_eq!(machine.ins_ptr, mem::Address(4));
+        assert_eq!(machine.memory, [1101, 40, 2, 4, 42].iter().into());
+    }
+
+    #[test]
+    fn test_negative_address_fault() {
+        let fault = run_to_fault(&[109, -5, 204, 1, 99]);
+        assert_eq!(fault.ins_ptr, mem::Address(2));
+        assert_eq!(
+            fault.instruction,
+            Some(op::Instruction::Output(op::Load::Relative(1.into())))
+        );
+        assert_eq!(
+            fault.cause,
+            vm::FaultCause::Address(mem::AddressError::Negative(-4))
+        );
+    }
+
+    #[test]
+    fn test_input_fault_leaves_input_unread() {
+        let mut machine = new_machine(&Program::from(&[109, -5, 203, 1, 99]));
+        machine.input.queue.push_back(7.into());
+        assert!(matches!(machine.run(), vm::Status::Faulted(_)));
+        assert_eq!(machine.input.queue, [7.into()]);
+        assert_eq!(machine.retired, 1);
+    }
+
+    #[test]
+    fn test_memory_limit_fault() {
+        let run_limited = |program: &[isize]| {
+            let mut machine = new_machine(&Program::from(program));
+            machine.memory.limit = 100;
+            match machine.run() {
+                vm::Status::Faulted(fault) => fault.cause,
+                status => panic!("expected fault, got {:?}", status),
+            }
+        };
+        let over_limit = |address| {
+            vm::FaultCause::Address(mem::AddressError::OverLimit {
+                address: mem::Address(address),
+                limit: 100,
+            })
+        };
+        assert_eq!(
+            run_limited(&[1101, 1, 2, 1_000_000_000, 99]),
+            over_limit(1_000_000_000)
+        );
+        assert_eq!(
+            run_limited(&[109, 50, 21101, 1, 1, 50, 99]),
+            over_limit(100)
+        );
+        assert_eq!(run_limited(&[4, 100, 99]), over_limit(100));
+        assert_eq!(
+            run_limited(&[109, -10, 204, 5, 99]),
+            vm::FaultCause::Address(mem::AddressError::Negative(-5))
+        );
+
+        let mut machine = new_machine(&Program::from(&[1101, 1, 2, 99, 99]));
+        machine.memory.limit = 100;
+        assert_eq!(machine.run(), vm::Status::Halted);
+    }
+
+    #[test]
+    fn test_overflow_fault() {
+        let fault = run_to_fault(&[1102, isize::MAX, 2, 0, 99]);
+        assert_eq!(fault.ins_ptr, mem::Address(0));
+        assert_eq!(fault.cause, vm::FaultCause::Overflow);
+    }
+
+    #[test]
+    fn test_jump_out_of_range_fault() {
+        let fault = run_to_fault(&[1105, 1, -3, 99]);
+        assert_eq!(fault.cause, vm::FaultCause::JumpOutOfRange((-3).into()));
+
+        let fault = run_to_fault(&[1106, 0, 100, 99]);
+        assert_eq!(fault.cause, vm::FaultCause::JumpOutOfRange(100.into()));
+    }
+}
+
+mod test_fuel {
+    use super::new_machine;
+    use crate::intcode::*;
+
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_run_with_fuel() {
+        let program = Program::from(&[1105, 1, 0]);
+        let mut machine = new_machine(&program);
+        assert_eq!(machine.run_with_fuel(10), vm::Status::OutOfFuel);
+        assert_eq!(machine.retired, 10);
+        assert_eq!(machine.run_with_fuel(5), vm::Status::OutOfFuel);
+        assert_eq!(machine.retired, 15);
+    }
+
+    #[test]
+    fn test_resume_after_out_of_fuel() {
+        let program = Program::from(&[3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8]);
+        let mut machine = new_machine(&program);
+        machine.input.queue.push_back(8.into());
+        assert_eq!(machine.run_with_fuel(2), vm::Status::OutOfFuel);
+        assert_eq!(machine.ins_ptr, mem::Address(6));
+        assert_eq!(machine.run_with_fuel(100), vm::Status::Halted);
+        assert_eq!(machine.output.buffer, [1.into()]);
+        assert_eq!(machine.retired, 4);
+    }
+
+    #[test]
+    fn test_no_fuel_keeps_stopped_status() {
+        let mut machine = new_machine(&Program::from(&[99]));
+        assert_eq!(machine.run(), vm::Status::Halted);
+        assert_eq!(machine.run_with_fuel(0), vm::Status::Halted);
+        assert_eq!(machine.step(), vm::Status::Halted);
+        assert_eq!(machine.retired, 1);
+
+        let mut machine = new_machine(&Program::from(&[1105, 1, -1]));
+        let status = machine.run();
+        assert!(matches!(status, vm::Status::Faulted(_)));
+        assert_eq!(machine.run_with_fuel(0), status);
+        assert_eq!(machine.status, status);
+
+        let mut machine = new_machine(&Program::from(&[3, 0, 99]));
+        assert_eq!(machine.run(), vm::Status::Blocked);
+        assert_eq!(machine.run_with_fuel(0), vm::Status::Blocked);
+        assert_eq!(machine.status, vm::Status::Blocked);
+        machine.input.queue.push_back(7.into());
+        assert_eq!(machine.run_with_fuel(0), vm::Status::Blocked);
+        assert_eq!(machine.run(), vm::Status::Halted);
+        assert_eq!(machine.retired, 2);
+    }
+
+    #[test]
+    fn test_blocked_input_is_not_retired() {
+        let program = Program::from(&[3, 0, 99]);
+        let mut machine = new_machine(&program);
+        assert_eq!(machine.run_with_fuel(10), vm::Status::Blocked);
+        assert_eq!(machine.retired, 0);
+    }
+
+    #[test]
+    fn test_run_until() {
+        let program = Program::from(&[1105, 1, 0]);
+        let mut machine = new_machine(&program);
+        let deadline = Instant::now() + Duration::from_millis(10);
+        assert_eq!(machine.run_until(deadline), vm::Status::OutOfFuel);
+        assert!(Instant::now() >= deadline);
+        assert!(machine.retired > 0);
+
+        let program = Program::from(&[104, 7, 99]);
+        let mut machine = new_machine(&program);
+        assert_eq!(machine.run_until(Instant::now()), vm::Status::Halted);
+    }
+}
+
+mod test_overflow {
+    use super::new_machine;
+    use crate::intcode::*;
+
+    use num::BigInt;
+
+    fn run_with(program: &[isize], overflow: mem::Overflow) -> (vm::Status, Vec<mem::Value>) {
+        let mut machine = new_machine(&Program::from(program));
+        machine.overflow = overflow;
+        let status = machine.run();
+        (status, machine.output.buffer)
+    }
+
+    #[test]
+    fn test_day09_large_numbers_under_every_policy() {
+        let policies = [
+            mem::Overflow::Trap,
+            mem::Overflow::Wrap,
+            mem::Overflow::Saturate,
+            mem::Overflow::Promote,
+        ];
+        for &overflow in policies.iter() {
+            let (status, output) = run_with(&[1102, 34915192, 34915192, 7, 4, 7, 99, 0], overflow);
+            assert_eq!(status, vm::Status::Halted);
+            assert_eq!(output, [1219070632396864.into()]);
+
+            let (status, output) = run_with(&[104, 1125899906842624, 99], overflow);
+            assert_eq!(status, vm::Status::Halted);
+            assert_eq!(output, [1125899906842624.into()]);
+        }
+    }
+
+    #[test]
+    fn test_overflowing_arithmetic() {
+        let square = [
+            1002,
+            9,
+            1125899906842624,
+            9,
+            4,
+            9,
+            99,
+            0,
+            0,
+            1125899906842624,
+        ];
+        let (status, _) = run_with(&square, mem::Overflow::Trap);
+        assert!(matches!(
+            status,
+            vm::Status::Faulted(vm::Fault {
+                cause: vm::FaultCause::Overflow,
+                ..
+            })
+        ));
+        let (_, output) = run_with(&square, mem::Overflow::Wrap);
+        assert_eq!(output, [0.into()]);
+        let (_, output) = run_with(&square, mem::Overflow::Saturate);
+        assert_eq!(output, [isize::MAX.into()]);
+
+        let negate_min = [1101, isize::MIN, -1, 7, 4, 7, 99, 0];
+        let (_, output) = run_with(&negate_min, mem::Overflow::Wrap);
+        assert_eq!(output, [isize::MAX.into()]);
+        let (_, output) = run_with(&negate_min, mem::Overflow::Saturate);
+        assert_eq!(output, [isize::MIN.into()]);
+    }
+
+    #[test]
+    fn test_promote_retries_overflowing_arithmetic() {
+        let program = Program::from(&[1102, 1125899906842624, 1125899906842624, 7, 4, 7, 99, 0]);
+        let mut machine = new_machine(&program);
+        machine.overflow = mem::Overflow::Promote;
+        machine.input.queue.push_back(5.into());
+        assert!(matches!(
+            machine.run(),
+            vm::Status::Faulted(vm::Fault {
+                cause: vm::FaultCause::Overflow,
+                ..
+            })
+        ));
+        let mut promoted = machine.promote::<mem::Dense<BigInt>>();
+        assert_eq!(promoted.status, vm::Status::Ready);
+        assert_eq!(promoted.input.queue, [mem::Value(BigInt::from(5))]);
+        assert_eq!(promoted.run(), vm::Status::Halted);
+        let exact = num::pow(BigInt::from(2), 100);
+        assert_eq!(promoted.output.buffer, [mem::Value(exact)]);
+        assert_eq!(promoted.retired, 3);
+
+        // Under `Trap`, the fault is kept.
+        let mut machine = new_machine(&program);
+        machine.run();
+        let promoted = machine.promote::<mem::Dense<BigInt>>();
+        assert!(matches!(
+            promoted.status,
+            vm::Status::Faulted(vm::Fault {
+                ins_ptr: mem::Address(0),
+                instruction: Some(_),
+                cause: vm::FaultCause::Overflow,
+            })
+        ));
+    }
+
+    #[test]
+    fn test_rel_base_overflow() {
+        let program = [109, isize::MAX, 109, 1, 99];
+        assert!(matches!(
+            run_with(&program, mem::Overflow::Trap).0,
+            vm::Status::Faulted(_)
+        ));
+        let mut machine = new_machine(&Program::from(&program));
+        machine.overflow = mem::Overflow::Saturate;
+        assert_eq!(machine.run(), vm::Status::Halted);
+        assert_eq!(machine.memory.rel_base, isize::MAX.into());
+    }
+}
+
+mod test_decode_cache {
+    use super::{new_machine, Backend};
+    use crate::intcode::*;
+
+    use std::any::TypeId;
+
+    #[test]
+    fn test_writes_invalidate_cached_instructions() {
+        fn read<B: mem::MemoryBackend<Word = isize>>(memory: &mut mem::Memory<B>) -> String {
+            let instruction = memory.read_cached_instruction(mem::Address(0));
+            instruction.unwrap().to_string()
+        }
+        let mut machine = new_machine(&Program::from(&[1101, 2, 3, 0, 99]));
+        let memory = &mut machine.memory;
+        assert_eq!(read(memory), "add #2, #3, 0");
+        memory[mem::Address(4)] = 1.into();
+        assert_eq!(read(memory), "add #2, #3, 0");
+        memory[mem::Address(2)] = 4.into();
+        assert_eq!(read(memory), "add #2, #4, 0");
+        memory[mem::Address(0)] = 1102.into();
+        assert_eq!(read(memory), "mul #2, #4, 0");
+    }
+
+    #[test]
+    fn test_truncate_drops_instructions_past_the_end() {
+        let mut machine = new_machine(&Program::from(&[99]));
+        let memory = &mut machine.memory;
+        for (address, value) in [(6, 1101), (7, 2), (8, 3), (9, 0)].iter() {
+            memory[mem::Address(*address)] = value.into();
+        }
+        let read = |memory: &mut mem::Memory<Backend>| {
+            let instruction = memory.read_cached_instruction(mem::Address(6));
+            instruction.map(|instruction| instruction.to_string())
+        };
+        assert_eq!(read(memory).unwrap(), "add #2, #3, 0");
+        memory.truncate(20);
+        assert_eq!(memory.len(), 10);
+        assert_eq!(read(memory).unwrap(), "add #2, #3, 0");
+        memory.truncate(8);
+        assert!(read(memory).is_err());
+    }
+
+    #[test]
+    fn test_self_modifying_loop() {
+        // Outputs its own operand, then increments it, 600 times over.
+        let program = Program::from(&[
+            104, 0, 1001, 1, 1, 1, 1007, 1, 600, 20, 1005, 20, 0, 99, 0, 0, 0, 0, 0, 0, 0,
+        ]);
+        let mut machine = new_machine(&program);
+        assert_eq!(machine.run(), vm::Status::Halted);
+        let expected = (0..600).map(mem::Value::from).collect::<Vec<_>>();
+        assert_eq!(machine.output.buffer, expected);
+    }
+
+    #[test]
+    fn test_far_jump_after_warmup() {
+        // Dense backends allocate every value up to the target, so only the
+        // paged ones jump as far as a billion.
+        let far = if TypeId::of::<Backend>() == TypeId::of::<mem::Paged>() {
+            1_000_000_000
+        } else {
+            1 << 20
+        };
+        // Writes `hlt` far away, counts down from 600, then jumps to it.
+        let program = Program::from(&[
+            1101, 99, 0, far, 1001, 20, -1, 20, 1005, 20, 4, 1105, 1, far, 0, 0, 0, 0, 0, 0, 600,
+        ]);
+        let mut machine = new_machine(&program);
+        assert_eq!(machine.run(), vm::Status::Halted);
+        assert_eq!(machine.ins_ptr, mem::Address(far as usize));
+        assert_eq!(machine.retired, 1203);
+    }
+}
@@ -0,0 +1,69 @@
+// Generated by intcode::transpile from a program of 16 values. Do not edit.
+
+const PROGRAM: &[isize] = &[
+    4, 0, 1001, 1, 1, 1, 1007, 1, 5, 15, 1005, 15, 0, 99, 0, 0,
+];
+
+const CODE: &[(usize, usize)] = &[
+    (0, 14),
+];
+
+/// Runs `machine`, which must be loaded with the program, like
+/// `Machine::run` does.
+// Short programs may never use `ok!`, or never loop back.
+#[allow(unused_macros, clippy::never_loop)]
+pub fn run<I, O, T, B>(machine: &mut intcode::Machine<I, O, T, B>) -> intcode::vm::Status
+where
+    I: intcode::vm::Input,
+    O: intcode::vm::Output,
+    T: intcode::trace::Tracer,
+    B: intcode::mem::MemoryBackend<Word = isize>,
+{
+    use intcode::native::*;
+
+    macro_rules! ok {
+        ($e:expr) => {
+            match $e {
+                Some(value) => value,
+                None => return machine.run(),
+            }
+        };
+    }
+
+    if !enter(machine, PROGRAM, CODE) {
+        return machine.run();
+    }
+    loop {
+        match machine.ins_ptr.0 {
+            0 => {
+                // 0: out 0
+                let value = ok!(load(machine, 0));
+                output(machine, value);
+                retire(machine, 2);
+                // 2: add 1, #1, 1
+                let value = ok!(add(machine, ok!(load(machine, 1)), 1));
+                let address = ok!(store(machine, CODE, 1));
+                set(machine, address, value);
+                retire(machine, 6);
+                // 6: lt 1, #5, 15
+                let value = less_than(ok!(load(machine, 1)), 5);
+                let address = ok!(store(machine, CODE, 15));
+                set(machine, address, value);
+                retire(machine, 10);
+                // 10: jt 15, #0
+                let x = ok!(load(machine, 15));
+                let target = ok!(jump(machine, 0));
+                if x != 0 {
+                    retire(machine, target);
+                } else {
+                    retire(machine, 13);
+                }
+            }
+            13 => {
+                // 13: hlt
+                return halt(machine);
+            }
+            _ => return machine.run(),
+        }
+    }
+}
@@ -0,0 +1,402 @@
+//! Running machines on threads of their own, passing values over
+//! `std::sync::mpsc` channels.
+//!
+//! A machine reading from a `ChannelInput` waits for a value to arrive
+//! instead of blocking, and only stops with `Status::Blocked` once every
+//! sender for its channel has gone. `spawn` closes a machine's output when it
+//! stops, so halting or faulting ends the machines downstream of it in turn.
+//!
+//! `run` does the same for the machines of a `network::Network`, and leaves
+//! the network as `Network::run` would. A machine's outputs are routed on the
+//! calling thread, which also notices when every machine that has not
+//! stopped is waiting for input that nothing has sent, and closes their
+//! inputs so that they stop too. A machine whose thread panics counts as
+//! stopped, and the panic is raised again on the calling thread once the
+//! rest of the network has stopped.
+
+use super::mem::{Dense, MemoryBackend, Value};
+use super::network::{Network, Outcome};
+use super::trace::{NoTracer, Tracer};
+use super::vm::{DefaultInput, DefaultOutput, Input, Machine, Output};
+
+use std::panic;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+
+/// Reads values from a channel, waiting for each one to be sent.
+#[derive(Debug)]
+pub struct ChannelInput<W = isize> {
+    pub receiver: Receiver<Value<W>>,
+}
+
+impl<W> ChannelInput<W> {
+    pub fn new(receiver: Receiver<Value<W>>) -> Self {
+        ChannelInput { receiver }
+    }
+}
+
+impl<W> Input<W> for ChannelInput<W> {
+    fn read_input(&mut self) -> Option<Value<W>> {
+        self.receiver.recv().ok()
+    }
+}
+
+/// Sends values to a channel until it is closed. Values sent after the
+/// receiver has gone are dropped.
+#[derive(Debug)]
+pub struct ChannelOutput<W = isize> {
+    pub sender: Option<Sender<Value<W>>>,
+}
+
+impl<W> ChannelOutput<W> {
+    pub fn new(sender: Sender<Value<W>>) -> Self {
+        ChannelOutput {
+            sender: Some(sender),
+        }
+    }
+
+    /// Drops the sender, so that a machine reading from the channel stops
+    /// once it has read everything sent so far.
+    pub fn close(&mut self) {
+        self.sender = None;
+    }
+}
+
+impl<W> Output<W> for ChannelOutput<W> {
+    fn write_output(&mut self, value: Value<W>) {
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(value);
+        }
+    }
+
+    /// Always `false`, as values are sent as soon as they are written.
+    fn output_ready(&self) -> bool {
+        false
+    }
+}
+
+pub type ChannelMachine<T = NoTracer, B = Dense> = Machine<
+    ChannelInput<<B as MemoryBackend>::Word>,
+    ChannelOutput<<B as MemoryBackend>::Word>,
+    T,
+    B,
+>;
+
+/// Runs `machine` on a new thread until it halts, faults, or runs out of
+/// input for good, then closes its output and hands it back.
+pub fn spawn<T, B>(mut machine: ChannelMachine<T, B>) -> JoinHandle<ChannelMachine<T, B>>
+where
+    T: Tracer<B::Word> + Send + 'static,
+    B: MemoryBackend + Send + 'static,
+    B::Word: Send + 'static,
+{
+    thread::spawn(move || {
+        machine.run();
+        machine.output.close();
+        machine
+    })
+}
+
+enum Event<W> {
+    Output(usize, Value<W>),
+    /// The machine has read the given number of values, and is waiting for
+    /// another.
+    Waiting(usize, usize),
+    Stopped(usize),
+}
+
+struct NodeInput<W> {
+    machine: usize,
+    read: usize,
+    receiver: Receiver<Value<W>>,
+    events: Sender<Event<W>>,
+}
+
+impl<W> Input<W> for NodeInput<W> {
+    fn read_input(&mut self) -> Option<Value<W>> {
+        let value = match self.receiver.try_recv() {
+            Ok(value) => value,
+            Err(TryRecvError::Empty) => {
+                let _ = self.events.send(Event::Waiting(self.machine, self.read));
+                self.receiver.recv().ok()?
+            }
+            Err(TryRecvError::Disconnected) => return None,
+        };
+        self.read += 1;
+        Some(value)
+    }
+}
+
+/// Reports that a machine's thread has finished when dropped, so that it is
+/// reported even if the thread panics.
+struct StopGuard<W> {
+    machine: usize,
+    events: Sender<Event<W>>,
+}
+
+impl<W> Drop for StopGuard<W> {
+    fn drop(&mut self) {
+        let _ = self.events.send(Event::Stopped(self.machine));
+    }
+}
+
+struct NodeOutput<W> {
+    machine: usize,
+    events: Sender<Event<W>>,
+}
+
+impl<W> Output<W> for NodeOutput<W> {
+    fn write_output(&mut self, value: Value<W>) {
+        let _ = self.events.send(Event::Output(self.machine, value));
+    }
+
+    fn output_ready(&self) -> bool {
+        false
+    }
+}
+
+/// Runs the machines of `network` until it goes quiet, each on a thread of
+/// its own, and returns what `Network::run` would.
+///
+/// Machines that share a sender may see values from different machines
+/// interleaved differently from run to run. Panics if the network has an
+/// `idle_input`, since machines here wait for input rather than poll.
+pub fn run<T, B>(network: &mut Network<T, B>) -> Outcome
+where
+    T: Tracer<B::Word> + Send + 'static,
+    B: MemoryBackend + Send + 'static,
+    B::Word: Send + 'static,
+{
+    assert!(
+        network.idle_input.is_none(),
+        "threaded networks cannot poll for input"
+    );
+    let len = network.machines.len();
+    let (events, receiver) = mpsc::channel();
+    let mut inputs = Vec::with_capacity(len);
+    let mut delivered = vec![0; len];
+    let mut waiting = vec![None; len];
+    let mut stopped = vec![false; len];
+    let mut threads = Vec::with_capacity(len);
+    for (index, mut machine) in network.machines.drain(..).enumerate() {
+        let (sender, receiver) = mpsc::channel();
+        for value in machine.input.queue.drain(..) {
+            sender.send(value).unwrap();
+            delivered[index] += 1;
+        }
+        for value in machine.output.buffer.drain(..) {
+            let _ = events.send(Event::Output(index, value));
+        }
+        inputs.push(Some(sender));
+        let input = NodeInput {
+            machine: index,
+            read: 0,
+            receiver,
+            events: events.clone(),
+        };
+        let output = NodeOutput {
+            machine: index,
+            events: events.clone(),
+        };
+        let guard = StopGuard {
+            machine: index,
+            events: events.clone(),
+        };
+        let mut machine = machine.with_io(input, output);
+        threads.push(thread::spawn(move || {
+            let _guard = guard;
+            machine.run();
+            machine
+        }));
+    }
+    drop(events);
+
+    let mut running = len;
+    while running > 0 {
+        let event = match receiver.recv() {
+            Ok(event) => event,
+            Err(_) => break,
+        };
+        match event {
+            Event::Output(from, value) => {
+                for to in network.wiring.destinations(from, &value, len) {
+                    network.senders[to].insert(from);
+                    delivered[to] += 1;
+                    if let Some(input) = &inputs[to] {
+                        let _ = input.send(value.clone());
+                    }
+                }
+                network.outputs[from].push(value);
+            }
+            Event::Waiting(machine, read) => waiting[machine] = Some(read),
+            Event::Stopped(machine) => {
+                stopped[machine] = true;
+                running -= 1;
+            }
+        }
+        let quiet =
+            (0..len).all(|index| stopped[index] || waiting[index] == Some(delivered[index]));
+        if quiet {
+            inputs.iter_mut().for_each(|input| *input = None);
+        }
+    }
+
+    for thread in threads {
+        let machine = thread
+            .join()
+            .unwrap_or_else(|err| panic::resume_unwind(err));
+        let queue = machine.input.receiver.try_iter().collect();
+        let input = DefaultInput { queue };
+        network
+            .machines
+            .push(machine.with_io(input, DefaultOutput::default()));
+    }
+    let idle = network.idle();
+    if idle.waiting.is_empty() {
+        Outcome::Stopped
+    } else {
+        Outcome::Deadlock(idle)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::intcode::network::Wiring;
+    use crate::intcode::*;
+
+    use itertools::Itertools;
+
+    /// Doubles every value it reads, forever.
+    const DOUBLER: &[isize] = &[3, 11, 1002, 11, 2, 11, 4, 11, 1105, 1, 0, 0];
+
+    fn connect(programs: &[&[isize]], wiring: Wiring) -> Network {
+        let machines = programs
+            .iter()
+            .map(|&program| Machine::default_io(&Program::from(program)))
+            .collect();
+        Network::new(machines, wiring)
+    }
+
+    /// Runs the network that `build` makes both on threads and with
+    /// `Network::run`, checking that they agree.
+    fn run_both(build: impl Fn() -> Network) -> (Network, Outcome) {
+        let mut network = build();
+        let outcome = network.run();
+        let mut threaded = build();
+        assert_eq!(run(&mut threaded), outcome);
+        assert_eq!(threaded.machines, network.machines);
+        assert_eq!(threaded.outputs, network.outputs);
+        (threaded, outcome)
+    }
+
+    #[test]
+    fn test_feedback_loop_matches_network() {
+        let program = include_str!("../../input/day07.in")
+            .parse::<Program>()
+            .unwrap();
+        for settings in (5..=9).permutations(5).step_by(7) {
+            let (network, outcome) = run_both(|| {
+                let machines = vec![Machine::default_io(&program); 5];
+                let mut network = Network::new(machines, Wiring::Ring);
+                for (machine, setting) in network.machines.iter_mut().zip(&settings) {
+                    machine.input.queue.push_back(setting.into());
+                }
+                network.inject(0, 0.into());
+                network
+            });
+            assert_eq!(outcome, Outcome::Stopped);
+            assert!(!network.outputs[4].is_empty());
+        }
+    }
+
+    #[test]
+    fn test_large_networks() {
+        let (network, outcome) = run_both(|| {
+            let mut network = connect(&[DOUBLER; 60], Wiring::Pipeline);
+            network.inject(0, 1.into());
+            network.inject(0, 3.into());
+            network
+        });
+        assert!(matches!(outcome, Outcome::Deadlock(_)));
+        assert_eq!(network.outputs[59], [(1 << 60).into(), (3 << 60).into()]);
+
+        // Doubles a value around the ring until it overflows.
+        let (network, outcome) = run_both(|| {
+            let mut network = connect(&[DOUBLER; 50], Wiring::Ring);
+            network.inject(0, 1.into());
+            network
+        });
+        assert!(matches!(outcome, Outcome::Deadlock(_)));
+        let faulted = network.machines.iter();
+        let faulted = faulted.filter(|machine| matches!(machine.status, vm::Status::Faulted(_)));
+        assert_eq!(faulted.count(), 1);
+    }
+
+    #[test]
+    fn test_halt_and_fault_shut_down() {
+        let source: &[isize] = &[104, 1, 104, 2, 99];
+        let faulty: &[isize] = &[3, 100, 4, 100, 1105, 1, -1];
+        let (network, outcome) =
+            run_both(|| connect(&[source, DOUBLER, faulty, DOUBLER], Wiring::Pipeline));
+        assert!(matches!(outcome, Outcome::Deadlock(_)));
+        assert_eq!(network.machines[0].status, vm::Status::Halted);
+        assert!(matches!(network.machines[2].status, vm::Status::Faulted(_)));
+        assert_eq!(network.machines[3].status, vm::Status::Blocked);
+        assert_eq!(network.outputs[2], [2.into()]);
+        assert_eq!(network.machines[2].input.queue, [4.into()]);
+    }
+
+    /// Panics on the first output of the machine it is given to.
+    #[derive(Clone, Debug, Default, Eq, PartialEq)]
+    struct PanicOnOutput(bool);
+
+    impl Tracer for PanicOnOutput {
+        fn trace(&mut self, trace: &trace::Trace) {
+            if self.0 && trace.instruction.opcode() == op::Opcode::Output {
+                panic!("output traced");
+            }
+        }
+    }
+
+    #[test]
+    fn test_panic_propagates() {
+        let machines = [&[104, 1, 99][..], DOUBLER, DOUBLER]
+            .iter()
+            .enumerate()
+            .map(|(index, &program)| {
+                Machine::default_io(&Program::from(program)).with_tracer(PanicOnOutput(index == 0))
+            })
+            .collect();
+        let mut network = Network::new(machines, Wiring::Pipeline);
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| run(&mut network)));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_spawn() {
+        let program = Program::from(DOUBLER);
+        let (first, receiver) = mpsc::channel();
+        let (sender, last) = mpsc::channel();
+        let mut receiver = Some(receiver);
+        let mut threads = vec![];
+        for index in 0..3 {
+            let (next, next_receiver) = mpsc::channel();
+            let output = if index == 2 { sender.clone() } else { next };
+            let machine = Machine::default_io(&program).with_io(
+                ChannelInput::new(receiver.take().unwrap()),
+                ChannelOutput::new(output),
+            );
+            threads.push(spawn(machine));
+            receiver = Some(next_receiver);
+        }
+        drop(sender);
+        first.send(1.into()).unwrap();
+        first.send(5.into()).unwrap();
+        drop(first);
+        assert_eq!(last.iter().collect::<Vec<_>>(), [8.into(), 40.into()]);
+        for thread in threads {
+            assert_eq!(thread.join().unwrap().status, vm::Status::Blocked);
+        }
+    }
+}
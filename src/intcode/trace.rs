@@ -0,0 +1,324 @@
+//! Per-step execution tracing for `vm::Machine`.
+//!
+//! A machine's tracer sees a `Trace` of every instruction it decodes,
+//! including ones that block or fault, whose traces say so and record only
+//! what happened before they stopped. Tracers declare whether they are
+//! enabled at compile time, so a machine with the default `NoTracer` does no
+//! tracing work at all.
+
+use super::mem::{self, Word};
+use super::op::Instruction;
+
+use std::io;
+
+/// A tracer for machines whose words are `W`. Tracers that only format
+/// values can be generic over `W`; most others only need the default.
+pub trait Tracer<W = isize> {
+    const ENABLED: bool = true;
+
+    fn trace(&mut self, trace: &Trace<W>);
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct NoTracer;
+
+impl<W> Tracer<W> for NoTracer {
+    const ENABLED: bool = false;
+
+    #[inline(always)]
+    fn trace(&mut self, _trace: &Trace<W>) {}
+}
+
+impl<W, T: Tracer<W>> Tracer<W> for &mut T {
+    const ENABLED: bool = T::ENABLED;
+
+    fn trace(&mut self, trace: &Trace<W>) {
+        (**self).trace(trace)
+    }
+}
+
+impl<W, A: Tracer<W>, B: Tracer<W>> Tracer<W> for (A, B) {
+    const ENABLED: bool = A::ENABLED || B::ENABLED;
+
+    fn trace(&mut self, trace: &Trace<W>) {
+        if A::ENABLED {
+            self.0.trace(trace);
+        }
+        if B::ENABLED {
+            self.1.trace(trace);
+        }
+    }
+}
+
+/// What a single instruction did: the values it loaded in operand order,
+/// the value it stored (and the value it overwrote), and the old and new
+/// relative base if it changed it. `memory_len` is the length of memory
+/// before the instruction, which a store past the end increases.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Trace<W = isize> {
+    pub ins_ptr: mem::Address,
+    pub instruction: Instruction<W>,
+    pub loads: [Option<mem::Value<W>>; 2],
+    pub store: Option<StoreTrace<W>>,
+    pub rel_base: Option<(mem::Offset, mem::Offset)>,
+    pub memory_len: usize,
+    pub completion: Completion,
+}
+
+/// How far an instruction got. Only `Retired` instructions changed the
+/// machine; the others left it as it was, apart from any input that a
+/// blocked machine waited for.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Completion {
+    Retired,
+    Blocked,
+    Faulted,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct StoreTrace<W = isize> {
+    pub address: mem::Address,
+    pub value: mem::Value<W>,
+    pub previous: mem::Value<W>,
+}
+
+impl<W: Word> Trace<W> {
+    pub(crate) fn new(
+        ins_ptr: mem::Address,
+        instruction: Instruction<W>,
+        memory_len: usize,
+    ) -> Self {
+        Trace {
+            ins_ptr,
+            instruction,
+            loads: [None, None],
+            store: None,
+            rel_base: None,
+            memory_len,
+            completion: Completion::Retired,
+        }
+    }
+
+    pub(crate) fn record_load(&mut self, value: mem::Value<W>) {
+        if let Some(slot) = self.loads.iter_mut().find(|slot| slot.is_none()) {
+            *slot = Some(value);
+        }
+    }
+
+    pub fn loaded(&self) -> impl Iterator<Item = mem::Value<W>> + '_ {
+        self.loads.iter().flatten().cloned()
+    }
+}
+
+/// Writes one human-readable line per instruction. Writing stops at the
+/// first I/O error, which is kept for the caller to inspect.
+#[derive(Debug)]
+pub struct TextTracer<W: io::Write> {
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl<W: io::Write> TextTracer<W> {
+    pub fn new(writer: W) -> Self {
+        TextTracer {
+            writer,
+            error: None,
+        }
+    }
+
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write_line<V: Word>(&mut self, trace: &Trace<V>) -> io::Result<()> {
+        let w = &mut self.writer;
+        let instruction = trace.instruction.to_string();
+        write!(w, "{:04}  {:<28}", trace.ins_ptr.0, instruction)?;
+        let loads = trace.loaded().map(|value| value.0.to_string());
+        let loads = loads.collect::<Vec<_>>();
+        if !loads.is_empty() {
+            write!(w, "  load {}", loads.join(", "))?;
+        }
+        if let Some(store) = &trace.store {
+            write!(
+                w,
+                "  store {} -> {} (was {})",
+                store.value.0, store.address.0, store.previous.0
+            )?;
+        }
+        if let Some((old, new)) = trace.rel_base {
+            write!(w, "  rel_base {} -> {}", old.0, new.0)?;
+        }
+        match trace.completion {
+            Completion::Retired => (),
+            Completion::Blocked => write!(w, "  blocked")?,
+            Completion::Faulted => write!(w, "  faulted")?,
+        }
+        writeln!(w)
+    }
+}
+
+impl<W: io::Write, V: Word> Tracer<V> for TextTracer<W> {
+    fn trace(&mut self, trace: &Trace<V>) {
+        if self.error.is_none() {
+            self.error = self.write_line(trace).err();
+        }
+    }
+}
+
+/// Writes one JSON object per instruction, in the JSON Lines format. Writing
+/// stops at the first I/O error, which is kept for the caller to inspect.
+#[derive(Debug)]
+pub struct JsonTracer<W: io::Write> {
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl<W: io::Write> JsonTracer<W> {
+    pub fn new(writer: W) -> Self {
+        JsonTracer {
+            writer,
+            error: None,
+        }
+    }
+
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write_line<V: Word>(&mut self, trace: &Trace<V>) -> io::Result<()> {
+        let w = &mut self.writer;
+        let encoded = trace.instruction.encode();
+        let encoded = encoded.iter().map(|value| value.0.to_string());
+        let loads = trace.loaded().map(|value| value.0.to_string());
+        write!(
+            w,
+            r#"{{"ins_ptr":{},"opcode":"{}","instruction":"{}","raw":[{}],"loads":[{}]"#,
+            trace.ins_ptr.0,
+            trace.instruction.opcode().mnemonic(),
+            trace.instruction,
+            encoded.collect::<Vec<_>>().join(","),
+            loads.collect::<Vec<_>>().join(",")
+        )?;
+        match &trace.store {
+            Some(store) => write!(
+                w,
+                r#","store":{{"address":{},"value":{},"previous":{}}}"#,
+                store.address.0, store.value.0, store.previous.0
+            )?,
+            None => write!(w, r#","store":null"#)?,
+        }
+        match trace.rel_base {
+            Some((old, new)) => write!(w, r#","rel_base":{{"old":{},"new":{}}}"#, old.0, new.0)?,
+            None => write!(w, r#","rel_base":null"#)?,
+        }
+        let completion = match trace.completion {
+            Completion::Retired => "retired",
+            Completion::Blocked => "blocked",
+            Completion::Faulted => "faulted",
+        };
+        writeln!(w, r#","completion":"{}"}}"#, completion)
+    }
+}
+
+impl<W: io::Write, V: Word> Tracer<V> for JsonTracer<W> {
+    fn trace(&mut self, trace: &Trace<V>) {
+        if self.error.is_none() {
+            self.error = self.write_line(trace).err();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::intcode::*;
+
+    #[derive(Debug, Default)]
+    struct Collect(Vec<Trace>);
+
+    impl Tracer for Collect {
+        fn trace(&mut self, trace: &Trace) {
+            self.0.push(*trace);
+        }
+    }
+
+    #[test]
+    fn test_trace_events() {
+        let program = Program::from(&[109, 10, 21101, 2, 3, 0, 204, 0, 99, 0, 7]);
+        let mut machine = Machine::default_io(&program).with_tracer(Collect::default());
+        assert_eq!(machine.run(), vm::Status::Halted);
+        let traces = machine.tracer.0;
+        assert_eq!(traces.len(), 4);
+
+        assert_eq!(traces[0].ins_ptr, mem::Address(0));
+        assert_eq!(traces[0].loads, [Some(10.into()), None]);
+        assert_eq!(traces[0].rel_base, Some((0.into(), 10.into())));
+
+        assert_eq!(traces[1].loads, [Some(2.into()), Some(3.into())]);
+        assert_eq!(
+            traces[1].store,
+            Some(StoreTrace {
+                address: mem::Address(10),
+                value: 5.into(),
+                previous: 7.into(),
+            })
+        );
+
+        assert_eq!(traces[2].instruction.to_string(), "out [0]");
+        assert_eq!(traces[2].loads, [Some(5.into()), None]);
+        assert_eq!(traces[3].instruction, op::Instruction::Halt);
+        assert!(traces.iter().all(|t| t.completion == Completion::Retired));
+    }
+
+    #[test]
+    fn test_blocked_and_faulted_instructions_are_traced() {
+        let program = Program::from(&[203, 0, 204, -5]);
+        let mut machine = Machine::default_io(&program).with_tracer(Collect::default());
+        assert_eq!(machine.run(), vm::Status::Blocked);
+        machine.input.queue.push_back(1.into());
+        assert!(matches!(machine.run(), vm::Status::Faulted(_)));
+        let completions = machine.tracer.0.iter().map(|t| (t.ins_ptr.0, t.completion));
+        assert_eq!(
+            completions.collect::<Vec<_>>(),
+            [
+                (0, Completion::Blocked),
+                (0, Completion::Retired),
+                (2, Completion::Faulted),
+            ]
+        );
+        assert_eq!(machine.tracer.0[2].loads, [None, None]);
+    }
+
+    #[test]
+    fn test_text_and_json_tracers() {
+        let program = Program::from(&[1101, 2, 3, 5, 99, 0]);
+        let tracers = (TextTracer::new(vec![]), JsonTracer::new(vec![]));
+        let mut machine = Machine::default_io(&program).with_tracer(tracers);
+        machine.run();
+        let (text, json) = machine.tracer;
+
+        let text = String::from_utf8(text.into_inner()).unwrap();
+        let lines = text.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines[0],
+            "0000  add #2, #3, 5                 load 2, 3  store 5 -> 5 (was 0)"
+        );
+        assert_eq!(lines[1].trim_end(), "0004  hlt");
+
+        let json = String::from_utf8(json.into_inner()).unwrap();
+        assert_eq!(
+            json.lines().next().unwrap(),
+            r#"{"ins_ptr":0,"opcode":"add","instruction":"add #2, #3, 5","raw":[1101,2,3,5],"loads":[2,3],"store":{"address":5,"value":5,"previous":0},"rel_base":null,"completion":"retired"}"#
+        );
+        assert_eq!(json.lines().count(), 2);
+    }
+}
@@ -0,0 +1,276 @@
+//! Translates Intcode programs into Rust source, for programs that are run
+//! often enough to be worth compiling natively.
+//!
+//! The generated source defines a function `run`, which runs a machine
+//! loaded with the program like `Machine::run` does. It is a state machine
+//! over the blocks of `intcode::cfg`, with one match arm per block and
+//! another before each `in`, so that a blocked machine can be resumed. It
+//! calls the helpers in `intcode::native`, and names the intcode module as
+//! `intcode`, so it must be included where that name is in scope:
+//!
+//! ```text
+//! mod native {
+//!     use aoc::intcode;
+//!     include!(concat!(env!("OUT_DIR"), "/day09.rs"));
+//! }
+//! ```
+//!
+//! A build script can generate the file with `transpile_file`. Whenever the
+//! generated code cannot go on (the interpreter would fault or block, the
+//! program writes over its own code, or it jumps somewhere that does not
+//! start a block) it hands the machine back to `Machine::run`, and a machine
+//! whose code has been overwritten is left to the interpreter.
+
+use super::cfg::Cfg;
+use super::op::{Instruction, Load, Opcode, Store};
+use super::{mem, Program, ProgramParseError};
+
+use std::collections::BTreeSet;
+use std::fmt::{self, Write as _};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const LINE_WIDTH: usize = 100;
+
+#[derive(Debug)]
+pub enum TranspileError {
+    Io(io::Error),
+    Parse(ProgramParseError),
+}
+
+impl fmt::Display for TranspileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TranspileError::Io(err) => write!(f, "{}", err),
+            TranspileError::Parse(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for TranspileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TranspileError::Io(err) => Some(err),
+            TranspileError::Parse(err) => Some(err),
+        }
+    }
+}
+
+impl From<io::Error> for TranspileError {
+    fn from(err: io::Error) -> Self {
+        TranspileError::Io(err)
+    }
+}
+
+impl From<ProgramParseError> for TranspileError {
+    fn from(err: ProgramParseError) -> Self {
+        TranspileError::Parse(err)
+    }
+}
+
+/// Reads the program at `input` and writes its translation to `output`,
+/// leaving `output` untouched if it is already up to date.
+pub fn transpile_file(
+    input: impl AsRef<Path>,
+    output: impl AsRef<Path>,
+) -> Result<(), TranspileError> {
+    let program = fs::read_to_string(input)?.parse::<Program>()?;
+    let source = transpile(&program);
+    if fs::read_to_string(&output).ok().as_ref() != Some(&source) {
+        fs::write(output, source)?;
+    }
+    Ok(())
+}
+
+pub fn transpile(program: &Program) -> String {
+    let cfg = Cfg::new(program);
+    let mut entries = BTreeSet::new();
+    let mut code = Vec::<(usize, usize)>::new();
+    for block in cfg.blocks() {
+        entries.insert(cfg[block].entry);
+        for &(address, instruction) in &cfg[block].instructions {
+            if let Instruction::Input(_) = instruction {
+                entries.insert(address);
+            }
+            let end = (address + instruction.opcode().len()).0;
+            match code.last_mut() {
+                Some((_, last_end)) if *last_end == address.0 => *last_end = end,
+                _ => code.push((address.0, end)),
+            }
+        }
+    }
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "// Generated by intcode::transpile from a program of {} values. Do not edit.",
+        program.0.len()
+    )
+    .unwrap();
+    out.push('\n');
+    let values = program.0.iter().map(|value| value.0.to_string());
+    write_slice(&mut out, "PROGRAM: &[isize]", values);
+    let ranges = code
+        .iter()
+        .map(|(start, end)| format!("({}, {})", start, end));
+    write_slice(&mut out, "CODE: &[(usize, usize)]", ranges);
+    out.push_str(PRELUDE);
+    let mut arms = Vec::<(mem::Address, String)>::new();
+    for block in cfg.blocks() {
+        for &(address, instruction) in &cfg[block].instructions {
+            if entries.contains(&address) {
+                arms.push((address, String::new()));
+            }
+            if let Some((_, body)) = arms.last_mut() {
+                write_instruction(body, address, instruction);
+            }
+        }
+    }
+    for (entry, body) in arms {
+        writeln!(out, "            {} => {{", entry.0).unwrap();
+        out.push_str(&body);
+        out.push_str("            }\n");
+    }
+    out.push_str(POSTLUDE);
+    out
+}
+
+const PRELUDE: &str = "/// Runs `machine`, which must be loaded with the program, like
+/// `Machine::run` does.
+// Short programs may never use `ok!`, or never loop back.
+#[allow(unused_macros, clippy::never_loop)]
+pub fn run<I, O, T, B>(machine: &mut intcode::Machine<I, O, T, B>) -> intcode::vm::Status
+where
+    I: intcode::vm::Input,
+    O: intcode::vm::Output,
+    T: intcode::trace::Tracer,
+    B: intcode::mem::MemoryBackend<Word = isize>,
+{
+    use intcode::native::*;
+
+    macro_rules! ok {
+        ($e:expr) => {
+            match $e {
+                Some(value) => value,
+                None => return machine.run(),
+            }
+        };
+    }
+
+    if !enter(machine, PROGRAM, CODE) {
+        return machine.run();
+    }
+    loop {
+        match machine.ins_ptr.0 {
+";
+
+const POSTLUDE: &str = "            _ => return machine.run(),
+        }
+    }
+}
+";
+
+fn write_slice(out: &mut String, declaration: &str, items: impl Iterator<Item = String>) {
+    writeln!(out, "const {} = &[", declaration).unwrap();
+    let mut line = String::new();
+    for item in items {
+        if !line.is_empty() && 4 + line.len() + item.len() + 2 > LINE_WIDTH {
+            writeln!(out, "    {}", line.trim_end()).unwrap();
+            line.clear();
+        }
+        write!(line, "{}, ", item).unwrap();
+    }
+    if !line.is_empty() {
+        writeln!(out, "    {}", line.trim_end()).unwrap();
+    }
+    out.push_str("];\n\n");
+}
+
+fn load(load: Load) -> String {
+    match load {
+        Load::Position(address) => format!("ok!(load(machine, {}))", address.0),
+        Load::Immediate(value) => value.0.to_string(),
+        Load::Relative(offset) => format!("ok!(load_relative(machine, {}))", offset.0),
+    }
+}
+
+fn store(store: Store) -> String {
+    match store {
+        Store::Position(address) => format!("ok!(store(machine, CODE, {}))", address.0),
+        Store::Relative(offset) => format!("ok!(store_relative(machine, CODE, {}))", offset.0),
+    }
+}
+
+fn write_instruction(out: &mut String, address: mem::Address, instruction: Instruction) {
+    let next = (address + instruction.opcode().len()).0;
+    let mut lines = vec![format!("// {}: {}", address.0, instruction)];
+    match instruction {
+        Instruction::Arith(opcode, lhs, rhs, result) => {
+            let (lhs, rhs) = (load(lhs), load(rhs));
+            lines.push(match opcode {
+                Opcode::Add => format!("let value = ok!(add(machine, {}, {}));", lhs, rhs),
+                Opcode::Multiply => format!("let value = ok!(mul(machine, {}, {}));", lhs, rhs),
+                Opcode::LessThan => format!("let value = less_than({}, {});", lhs, rhs),
+                _ => format!("let value = equals({}, {});", lhs, rhs),
+            });
+            lines.push(format!("let address = {};", store(result)));
+            lines.push("set(machine, address, value);".to_owned());
+            lines.push(format!("retire(machine, {});", next));
+        }
+        Instruction::CondJump(opcode, x, target) => {
+            let taken = match x {
+                Load::Immediate(value) => Some(opcode.cond_jump_fn()(value)),
+                _ => None,
+            };
+            let jump = match taken {
+                Some(false) if !matches!(target, Load::Immediate(_)) => {
+                    lines.push(format!("{};", load(target)));
+                    None
+                }
+                Some(false) => None,
+                _ => Some(format!("ok!(jump(machine, {}))", load(target))),
+            };
+            match (taken, jump) {
+                (None, Some(jump)) => {
+                    let condition = match opcode {
+                        Opcode::JumpIfTrue => "!=",
+                        _ => "==",
+                    };
+                    lines.push(format!("let x = {};", load(x)));
+                    lines.push(format!("let target = {};", jump));
+                    lines.push(format!("if x {} 0 {{", condition));
+                    lines.push("    retire(machine, target);".to_owned());
+                    lines.push("} else {".to_owned());
+                    lines.push(format!("    retire(machine, {});", next));
+                    lines.push("}".to_owned());
+                }
+                (_, Some(jump)) => {
+                    lines.push(format!("let target = {};", jump));
+                    lines.push("retire(machine, target);".to_owned());
+                }
+                (_, None) => lines.push(format!("retire(machine, {});", next)),
+            }
+        }
+        Instruction::Input(store_input) => {
+            lines.push(format!("let address = {};", store(store_input)));
+            lines.push("let value = ok!(input(machine));".to_owned());
+            lines.push("set(machine, address, value);".to_owned());
+            lines.push(format!("retire(machine, {});", next));
+        }
+        Instruction::Output(value) => {
+            lines.push(format!("let value = {};", load(value)));
+            lines.push("output(machine, value);".to_owned());
+            lines.push(format!("retire(machine, {});", next));
+        }
+        Instruction::SetRelBase(value) => {
+            lines.push(format!("let value = {};", load(value)));
+            lines.push("ok!(set_rel_base(machine, value));".to_owned());
+            lines.push(format!("retire(machine, {});", next));
+        }
+        Instruction::Halt => lines.push("return halt(machine);".to_owned()),
+    }
+    for line in lines {
+        writeln!(out, "                {}", line).unwrap();
+    }
+}
@@ -1,43 +1,105 @@
-use super::mem::Memory;
+use super::mem::{Dense, Memory, MemoryBackend, Word};
 use super::op::Instruction;
+use super::trace::{Completion, NoTracer, StoreTrace, Trace, Tracer};
 use crate::intcode::*;
 
+use num::BigInt;
+use serde_derive::{Deserialize, Serialize};
 use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::fmt;
+use std::time::Instant;
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
-pub enum Status {
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum Status<W = isize> {
     Ready,
     Blocked,
     Halted,
+    Faulted(Fault<W>),
+    OutOfFuel,
 }
 
-pub trait Input {
-    fn read_input(&mut self) -> Option<mem::Value>;
+/// The state of a machine when it stopped on a bad instruction. The
+/// instruction is `None` if it could not be decoded.
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Fault<W = isize> {
+    pub ins_ptr: mem::Address,
+    pub instruction: Option<Instruction<W>>,
+    pub cause: FaultCause<W>,
+}
+
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum FaultCause<W = isize> {
+    Decode(op::DecodeError<W>),
+    Address(mem::AddressError),
+    Overflow,
+    JumpOutOfRange(mem::Value<W>),
+}
+
+impl<W> From<mem::AddressError> for FaultCause<W> {
+    fn from(err: mem::AddressError) -> Self {
+        FaultCause::Address(err)
+    }
+}
+
+impl<W: Word> fmt::Display for Fault<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "fault at address {}", self.ins_ptr.0)?;
+        if let Some(instruction) = &self.instruction {
+            write!(f, " ({:?})", instruction)?;
+        }
+        match &self.cause {
+            FaultCause::Decode(err) => write!(f, ": {}", err),
+            FaultCause::Address(err) => write!(f, ": {}", err),
+            FaultCause::Overflow => write!(f, ": arithmetic overflow"),
+            FaultCause::JumpOutOfRange(target) => write!(f, ": jump out of range to {}", target.0),
+        }
+    }
 }
 
-pub trait Output {
-    fn write_output(&mut self, value: mem::Value);
+impl<W: Word> std::error::Error for Fault<W> {}
+
+pub trait Input<W = isize> {
+    fn read_input(&mut self) -> Option<mem::Value<W>>;
+}
+
+pub trait Output<W = isize> {
+    fn write_output(&mut self, value: mem::Value<W>);
     fn output_ready(&self) -> bool;
 }
 
-#[derive(Clone, Debug, Default, Eq, PartialEq)]
-pub struct DefaultInput {
-    pub queue: VecDeque<mem::Value>,
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct DefaultInput<W = isize> {
+    pub queue: VecDeque<mem::Value<W>>,
 }
 
-impl Input for DefaultInput {
-    fn read_input(&mut self) -> Option<mem::Value> {
+impl<W> Default for DefaultInput<W> {
+    fn default() -> Self {
+        DefaultInput {
+            queue: VecDeque::new(),
+        }
+    }
+}
+
+impl<W> Input<W> for DefaultInput<W> {
+    fn read_input(&mut self) -> Option<mem::Value<W>> {
         self.queue.pop_front()
     }
 }
 
-#[derive(Clone, Debug, Default, Eq, PartialEq)]
-pub struct DefaultOutput {
-    pub buffer: Vec<mem::Value>,
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct DefaultOutput<W = isize> {
+    pub buffer: Vec<mem::Value<W>>,
 }
 
-impl Output for DefaultOutput {
-    fn write_output(&mut self, value: mem::Value) {
+impl<W> Default for DefaultOutput<W> {
+    fn default() -> Self {
+        DefaultOutput { buffer: Vec::new() }
+    }
+}
+
+impl<W> Output<W> for DefaultOutput<W> {
+    fn write_output(&mut self, value: mem::Value<W>) {
         self.buffer.push(value);
     }
 
@@ -46,17 +108,45 @@ impl Output for DefaultOutput {
     }
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
-pub struct Machine<I = DefaultInput, O = DefaultOutput>
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(bound(
+    serialize = "I: serde::Serialize, O: serde::Serialize, B: serde::Serialize, \
+                 B::Word: serde::Serialize",
+    deserialize = "I: serde::Deserialize<'de>, O: serde::Deserialize<'de>, T: Default, \
+                   B: serde::Deserialize<'de>, B::Word: serde::Deserialize<'de>"
+))]
+pub struct Machine<I = DefaultInput, O = DefaultOutput, T = NoTracer, B = Dense>
 where
-    I: Input,
-    O: Output,
+    I: Input<B::Word>,
+    O: Output<B::Word>,
+    T: Tracer<B::Word>,
+    B: MemoryBackend,
 {
-    pub status: Status,
-    pub memory: Memory,
+    pub status: Status<B::Word>,
+    pub memory: Memory<B>,
     pub ins_ptr: mem::Address,
     pub input: I,
     pub output: O,
+    /// The number of instructions executed to completion.
+    pub retired: u64,
+    /// What `add`, `mul` and `arb` do when their result overflows.
+    pub overflow: mem::Overflow,
+    #[serde(skip)]
+    pub engine: Engine,
+    #[serde(skip)]
+    pub tracer: T,
+}
+
+/// How `run` and its variants execute instructions. Both engines leave a
+/// machine in the same state; `step` always uses the reference interpreter.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum Engine {
+    /// Decodes and executes one instruction at a time with `step`.
+    #[default]
+    Reference,
+    /// Runs instructions compiled by `fast`. Machines with an enabled tracer
+    /// use the reference interpreter instead.
+    Fast,
 }
 
 impl Machine<DefaultInput, DefaultOutput> {
@@ -65,83 +155,320 @@ impl Machine<DefaultInput, DefaultOutput> {
     }
 }
 
-impl<I, O> Machine<I, O>
+impl<I, O, T, B> Machine<I, O, T, B>
 where
-    I: Input + Default,
-    O: Output + Default,
+    I: Input<B::Word> + Default,
+    O: Output<B::Word> + Default,
+    T: Tracer<B::Word> + Default,
+    B: MemoryBackend,
 {
-    pub fn new(program: &Program) -> Self {
+    pub fn new(program: &Program<B::Word>) -> Self {
         Machine {
             status: Status::Ready,
-            memory: program.0.clone().into(),
+            memory: program.into(),
             ins_ptr: mem::Address(0),
             input: I::default(),
             output: O::default(),
+            retired: 0,
+            overflow: mem::Overflow::default(),
+            engine: Engine::default(),
+            tracer: T::default(),
+        }
+    }
+}
+
+impl<I, O, T, B> Machine<I, O, T, B>
+where
+    I: Input<B::Word>,
+    O: Output<B::Word>,
+    T: Tracer<B::Word>,
+    B: MemoryBackend,
+{
+    /// Replaces the machine's tracer, which sees every instruction executed
+    /// from now on.
+    pub fn with_tracer<U: Tracer<B::Word>>(self, tracer: U) -> Machine<I, O, U, B> {
+        Machine {
+            status: self.status,
+            memory: self.memory,
+            ins_ptr: self.ins_ptr,
+            input: self.input,
+            output: self.output,
+            retired: self.retired,
+            overflow: self.overflow,
+            engine: self.engine,
+            tracer,
+        }
+    }
+
+    /// Replaces the machine's input and output, keeping everything else.
+    pub fn with_io<J, P>(self, input: J, output: P) -> Machine<J, P, T, B>
+    where
+        J: Input<B::Word>,
+        P: Output<B::Word>,
+    {
+        Machine {
+            status: self.status,
+            memory: self.memory,
+            ins_ptr: self.ins_ptr,
+            input,
+            output,
+            retired: self.retired,
+            overflow: self.overflow,
+            engine: self.engine,
+            tracer: self.tracer,
         }
     }
 }
 
+impl<T, B> Machine<DefaultInput<B::Word>, DefaultOutput<B::Word>, T, B>
+where
+    T: Tracer<B::Word> + Tracer<BigInt>,
+    B: MemoryBackend,
+{
+    /// Converts the machine to `BigInt` words, with memory in backend `C`.
+    /// A machine that faulted on an overflowing `add` or `mul` under
+    /// `Overflow::Promote` is made ready to retry it; any other status is
+    /// kept.
+    pub fn promote<C: MemoryBackend<Word = BigInt>>(
+        self,
+    ) -> Machine<DefaultInput<BigInt>, DefaultOutput<BigInt>, T, C> {
+        let memory = self.memory.promote::<C>();
+        let status = match self.status {
+            Status::Ready => Status::Ready,
+            Status::Blocked => Status::Blocked,
+            Status::Halted => Status::Halted,
+            Status::OutOfFuel => Status::OutOfFuel,
+            Status::Faulted(Fault {
+                instruction: Some(Instruction::Arith(..)),
+                cause: FaultCause::Overflow,
+                ..
+            }) if self.overflow == mem::Overflow::Promote => Status::Ready,
+            Status::Faulted(fault) => Status::Faulted(Fault {
+                ins_ptr: fault.ins_ptr,
+                instruction: fault
+                    .instruction
+                    .as_ref()
+                    .and_then(|_| memory.read_instruction(fault.ins_ptr).ok()),
+                cause: match fault.cause {
+                    FaultCause::Decode(err) => FaultCause::Decode(op::DecodeError {
+                        address: err.address,
+                        value: mem::Value(err.value.0.to_bigint()),
+                        kind: err.kind,
+                    }),
+                    FaultCause::Address(err) => FaultCause::Address(err),
+                    FaultCause::Overflow => FaultCause::Overflow,
+                    FaultCause::JumpOutOfRange(target) => {
+                        FaultCause::JumpOutOfRange(mem::Value(target.0.to_bigint()))
+                    }
+                },
+            }),
+        };
+        let promote = |value: &mem::Value<B::Word>| mem::Value(value.0.to_bigint());
+        Machine {
+            status,
+            memory,
+            ins_ptr: self.ins_ptr,
+            input: DefaultInput {
+                queue: self.input.queue.iter().map(promote).collect(),
+            },
+            output: DefaultOutput {
+                buffer: self.output.buffer.iter().map(promote).collect(),
+            },
+            retired: self.retired,
+            overflow: self.overflow,
+            engine: self.engine,
+            tracer: self.tracer,
+        }
+    }
+}
+
+const DEADLINE_CHECK_INTERVAL: u64 = 4096;
+
+/// How many instructions a machine runs before it starts caching decoded
+/// instructions, so that machines that only run briefly do not pay for a
+/// cache they would barely use.
+const CACHE_WARMUP: u64 = 1024;
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
-enum InsPtrUpdate {
+enum InsPtrUpdate<W> {
     Jump(mem::Address),
     Advance(mem::Offset),
+    Stop(Status<W>),
 }
 
-impl<I: Input, O: Output> Machine<I, O> {
-    pub fn step(&mut self) -> Status {
-        if let Status::Halted = self.status {
-            return self.status;
+impl<I, O, T, B> Machine<I, O, T, B>
+where
+    I: Input<B::Word>,
+    O: Output<B::Word>,
+    T: Tracer<B::Word>,
+    B: MemoryBackend,
+{
+    /// Executes a single instruction. A machine that has halted or faulted
+    /// stays that way, without changing state.
+    pub fn step(&mut self) -> Status<B::Word> {
+        if let Status::Halted | Status::Faulted(_) = self.status {
+            return self.status.clone();
+        }
+        let instruction = if self.retired < CACHE_WARMUP {
+            self.memory.read_instruction(self.ins_ptr)
+        } else {
+            self.memory.read_cached_instruction(self.ins_ptr)
+        };
+        let instruction = match instruction {
+            Ok(instruction) => instruction,
+            Err(err) => return self.fault(None, FaultCause::Decode(err)),
+        };
+        let mut trace = if T::ENABLED {
+            Some(Trace::new(
+                self.ins_ptr,
+                instruction.clone(),
+                self.memory.len(),
+            ))
+        } else {
+            None
+        };
+        let update = match self.execute(instruction.clone(), &mut trace) {
+            Ok(update) => update,
+            Err(cause) => {
+                self.trace(trace, Completion::Faulted);
+                return self.fault(Some(instruction), cause);
+            }
+        };
+        let status = match update {
+            InsPtrUpdate::Stop(Status::Blocked) => {
+                self.trace(trace, Completion::Blocked);
+                self.status = Status::Blocked;
+                return Status::Blocked;
+            }
+            InsPtrUpdate::Jump(address) => {
+                self.ins_ptr = address;
+                Status::Ready
+            }
+            InsPtrUpdate::Advance(amount) => {
+                self.ins_ptr += amount;
+                Status::Ready
+            }
+            InsPtrUpdate::Stop(status) => status,
+        };
+        self.trace(trace, Completion::Retired);
+        self.retired += 1;
+        self.status = status;
+        self.status.clone()
+    }
+
+    fn trace(&mut self, trace: Option<Trace<B::Word>>, completion: Completion) {
+        if let Some(mut trace) = trace {
+            trace.completion = completion;
+            self.tracer.trace(&trace);
+        }
+    }
+
+    fn load(
+        &self,
+        load: op::Load<B::Word>,
+        trace: &mut Option<Trace<B::Word>>,
+    ) -> Result<mem::Value<B::Word>, FaultCause<B::Word>> {
+        let value = self.memory.load(load)?;
+        if let Some(trace) = trace {
+            trace.record_load(value.clone());
         }
-        let instruction = self.memory.read_instruction(self.ins_ptr);
+        Ok(value)
+    }
+
+    fn store(
+        &mut self,
+        value: mem::Value<B::Word>,
+        store: op::Store,
+        trace: &mut Option<Trace<B::Word>>,
+    ) -> Result<(), FaultCause<B::Word>> {
+        if let Some(trace) = trace {
+            let address = self.memory.store_address(store)?;
+            trace.store = Some(StoreTrace {
+                address,
+                value: value.clone(),
+                previous: self.memory[address].clone(),
+            });
+        }
+        self.memory.store(value, store)?;
+        Ok(())
+    }
+
+    fn execute(
+        &mut self,
+        instruction: Instruction<B::Word>,
+        trace: &mut Option<Trace<B::Word>>,
+    ) -> Result<InsPtrUpdate<B::Word>, FaultCause<B::Word>> {
+        let len = instruction.opcode().len();
         let update = match instruction {
             Instruction::Arith(opcode, load_lhs, load_rhs, store_result) => {
-                let lhs = self.memory.load(load_lhs);
-                let rhs = self.memory.load(load_rhs);
-                let result = opcode.arith_fn()(lhs, rhs);
-                self.memory.store(result, store_result);
+                let lhs = self.load(load_lhs, trace)?;
+                let rhs = self.load(load_rhs, trace)?;
+                let result =
+                    opcode.arith_fn()(lhs, rhs, self.overflow).ok_or(FaultCause::Overflow)?;
+                self.store(result, store_result, trace)?;
                 InsPtrUpdate::Advance(opcode.len())
             }
             Instruction::CondJump(opcode, load_x, load_addr) => {
-                let x = self.memory.load(load_x);
-                let addr = self.memory.load(load_addr).into();
+                let x = self.load(load_x, trace)?;
+                let target = self.load(load_addr, trace)?;
                 if opcode.cond_jump_fn()(x) {
-                    InsPtrUpdate::Jump(addr)
+                    match mem::Address::try_from(target.clone()) {
+                        Ok(address) if address.0 < self.memory.len() => InsPtrUpdate::Jump(address),
+                        _ => return Err(FaultCause::JumpOutOfRange(target)),
+                    }
                 } else {
                     InsPtrUpdate::Advance(opcode.len())
                 }
             }
             Instruction::Input(store_input) => {
+                // Checked before reading, so that a fault leaves the input
+                // unread.
+                self.memory.store_address(store_input)?;
                 if let Some(input) = self.input.read_input() {
-                    self.memory.store(input, store_input);
-                    InsPtrUpdate::Advance(instruction.opcode().len())
+                    self.store(input, store_input, trace)?;
+                    InsPtrUpdate::Advance(len)
                 } else {
-                    self.status = Status::Blocked;
-                    return self.status;
+                    InsPtrUpdate::Stop(Status::Blocked)
                 }
             }
             Instruction::Output(load_output) => {
-                let output = self.memory.load(load_output);
+                let output = self.load(load_output, trace)?;
                 self.output.write_output(output);
-                InsPtrUpdate::Advance(instruction.opcode().len())
+                InsPtrUpdate::Advance(len)
             }
             Instruction::SetRelBase(load_addr) => {
-                let addr = self.memory.load(load_addr);
-                self.memory.rel_base += mem::Offset::from(addr);
-                InsPtrUpdate::Advance(instruction.opcode().len())
-            }
-            Instruction::Halt => {
-                self.status = Status::Halted;
-                return self.status;
+                let addr = self.load(load_addr, trace)?;
+                let old = self.memory.rel_base;
+                let rel_base = old.add_with(addr, self.overflow);
+                self.memory.rel_base = rel_base.ok_or(FaultCause::Overflow)?;
+                if let Some(trace) = trace {
+                    trace.rel_base = Some((old, self.memory.rel_base));
+                }
+                InsPtrUpdate::Advance(len)
             }
+            Instruction::Halt => InsPtrUpdate::Stop(Status::Halted),
         };
-        match update {
-            InsPtrUpdate::Jump(address) => self.ins_ptr = address,
-            InsPtrUpdate::Advance(amount) => self.ins_ptr += amount,
-        }
-        Status::Ready
+        Ok(update)
     }
 
-    pub fn run(&mut self) -> Status {
+    fn fault(
+        &mut self,
+        instruction: Option<Instruction<B::Word>>,
+        cause: FaultCause<B::Word>,
+    ) -> Status<B::Word> {
+        self.status = Status::Faulted(Fault {
+            ins_ptr: self.ins_ptr,
+            instruction,
+            cause,
+        });
+        self.status.clone()
+    }
+
+    /// Runs until the machine blocks, halts or faults.
+    pub fn run(&mut self) -> Status<B::Word> {
+        if self.engine == Engine::Fast && !T::ENABLED {
+            return self.run_fast(None);
+        }
         loop {
             match self.step() {
                 Status::Ready => continue,
@@ -149,4 +476,37 @@ impl<I: Input, O: Output> Machine<I, O> {
             }
         }
     }
+
+    /// Runs like `run`, but executes at most `fuel` instructions before
+    /// stopping with `Status::OutOfFuel`. The machine can then be resumed.
+    pub fn run_with_fuel(&mut self, fuel: u64) -> Status<B::Word> {
+        match self.status {
+            Status::Halted | Status::Faulted(_) => return self.status.clone(),
+            // Without fuel, a blocked machine cannot retry its input.
+            Status::Blocked if fuel == 0 => return Status::Blocked,
+            _ => {}
+        }
+        if self.engine == Engine::Fast && !T::ENABLED {
+            return self.run_fast(Some(fuel));
+        }
+        for _ in 0..fuel {
+            match self.step() {
+                Status::Ready => continue,
+                stopped => return stopped,
+            }
+        }
+        self.status = Status::OutOfFuel;
+        Status::OutOfFuel
+    }
+
+    /// Runs like `run`, but stops with `Status::OutOfFuel` once `deadline`
+    /// has passed. The clock is only checked every few thousand steps.
+    pub fn run_until(&mut self, deadline: Instant) -> Status<B::Word> {
+        loop {
+            match self.run_with_fuel(DEADLINE_CHECK_INTERVAL) {
+                Status::OutOfFuel if Instant::now() < deadline => continue,
+                stopped => return stopped,
+            }
+        }
+    }
 }